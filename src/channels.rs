@@ -0,0 +1,147 @@
+//! Channel identifier table.
+
+use p25::trunking::fields::{self, Channel, ChannelParams, ChannelParamsMap};
+
+/// Number of possible channel identifiers.
+const IDENTIFIERS: u8 = 16;
+
+/// User-configured parameters for a channel identifier.
+#[derive(Copy, Clone, Deserialize)]
+pub struct ChannelConfig {
+    /// Channel identifier (0-15).
+    pub id: u8,
+    /// Base frequency (Hz).
+    pub base: u32,
+    /// Channel spacing (Hz).
+    pub spacing: u32,
+    /// Transmit offset (Hz).
+    #[serde(default)]
+    pub offset: i32,
+    /// Channel bandwidth (Hz).
+    #[serde(default)]
+    pub bandwidth: u32,
+    /// Whether these parameters override any broadcast by the site, rather than only
+    /// seeding the identifier until a broadcast is received.
+    #[serde(default)]
+    pub fixed: bool,
+}
+
+impl ChannelConfig {
+    /// Convert to channel parameters.
+    fn params(&self) -> ChannelParams {
+        ChannelParams {
+            base: self.base,
+            spacing: self.spacing,
+            offset: self.offset,
+            bandwidth: self.bandwidth,
+        }
+    }
+}
+
+/// Where the parameters for a channel identifier came from.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize)]
+pub enum ChannelSource {
+    /// Identifier update broadcast by the site.
+    #[serde(rename = "broadcast")]
+    Broadcast,
+    /// User configuration.
+    #[serde(rename = "config")]
+    Config,
+}
+
+/// Maps channel identifiers to parameters, combining identifier update broadcasts with
+/// user-configured entries.
+pub struct ChannelTable {
+    /// Parameters learned from identifier update broadcasts.
+    learned: ChannelParamsMap,
+    /// User-configured parameters.
+    config: Vec<ChannelConfig>,
+}
+
+impl ChannelTable {
+    /// Create a new `ChannelTable` with the given user-configured entries.
+    pub fn new(config: Vec<ChannelConfig>) -> Self {
+        ChannelTable {
+            learned: ChannelParamsMap::default(),
+            config: config,
+        }
+    }
+
+    /// Record the given identifier update broadcast.
+    pub fn update(&mut self, u: &fields::ChannelParamsUpdate) {
+        self.learned.update(u);
+    }
+
+    /// Forget all broadcast parameters, such as when moving to a different site.
+    pub fn reset(&mut self) {
+        self.learned = ChannelParamsMap::default();
+    }
+
+    /// Look up the parameters and their source for the given channel identifier.
+    pub fn lookup_source(&self, id: u8) -> Option<(ChannelParams, ChannelSource)> {
+        let conf = self.config.iter().find(|c| c.id == id);
+
+        if let Some(c) = conf {
+            if c.fixed {
+                return Some((c.params(), ChannelSource::Config));
+            }
+        }
+
+        match self.learned.lookup(id) {
+            Some(p) => Some((p, ChannelSource::Broadcast)),
+            None => conf.map(|c| (c.params(), ChannelSource::Config)),
+        }
+    }
+
+    /// Look up the parameters for the given channel identifier.
+    pub fn lookup(&self, id: u8) -> Option<ChannelParams> {
+        self.lookup_source(id).map(|(p, _)| p)
+    }
+
+    /// Look up the receive frequency (Hz) of the given channel.
+    pub fn rx_freq(&self, ch: Channel) -> Option<u32> {
+        self.lookup(ch.id()).map(|p| p.rx_freq(ch.number()))
+    }
+
+    /// Iterate over all known identifiers with their parameters and source.
+    pub fn entries<'a>(&'a self)
+        -> impl Iterator<Item = (u8, ChannelParams, ChannelSource)> + 'a
+    {
+        (0..IDENTIFIERS).filter_map(move |id| {
+            self.lookup_source(id).map(|(p, src)| (id, p, src))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_config() {
+        let t = ChannelTable::new(vec![
+            ChannelConfig {
+                id: 1,
+                base: 851000000,
+                spacing: 12500,
+                offset: -45000000,
+                bandwidth: 12500,
+                fixed: false,
+            },
+            ChannelConfig {
+                id: 2,
+                base: 935000000,
+                spacing: 12500,
+                offset: -39000000,
+                bandwidth: 12500,
+                fixed: true,
+            },
+        ]);
+
+        assert!(t.lookup(0).is_none());
+        assert_eq!(t.lookup_source(1).unwrap().1, ChannelSource::Config);
+        assert_eq!(t.lookup(1).unwrap().base, 851000000);
+        assert_eq!(t.lookup_source(2).unwrap().1, ChannelSource::Config);
+        assert_eq!(t.entries().map(|(id, _, _)| id).collect::<Vec<_>>(), vec![1, 2]);
+    }
+}
@@ -0,0 +1,53 @@
+//! Receiver configuration file.
+
+use std::io::Read;
+
+use serde_json;
+
+use channels::ChannelConfig;
+use talkgroups::{ScanLists, UnitFilter};
+
+/// Options loaded from a JSON configuration file.
+#[derive(Default, Deserialize)]
+pub struct Config {
+    /// Channel identifier parameters to seed or override.
+    #[serde(default)]
+    pub channels: Vec<ChannelConfig>,
+    /// Known control channel frequencies (Hz) to fall back to on control channel loss.
+    #[serde(default)]
+    pub ctlfreqs: Vec<u32>,
+    /// Identity a control channel must match before it's used.
+    #[serde(default)]
+    pub system: SystemIdentity,
+    /// Private calls to follow.
+    #[serde(default)]
+    pub private: UnitFilter,
+    /// Named talkgroup scan lists.
+    #[serde(default)]
+    pub scanlists: ScanLists,
+    /// Scan list active at startup, if any.
+    #[serde(default)]
+    pub scanlist: Option<String>,
+}
+
+/// Expected identity of the monitored system, where each `None` field matches
+/// anything.
+#[derive(Copy, Clone, Default, Deserialize)]
+pub struct SystemIdentity {
+    /// Wide area communications network ID.
+    #[serde(default)]
+    pub wacn: Option<u32>,
+    /// System ID.
+    #[serde(default)]
+    pub system: Option<u16>,
+    /// Network access code.
+    #[serde(default)]
+    pub nac: Option<u16>,
+}
+
+impl Config {
+    /// Parse a configuration from the given JSON stream.
+    pub fn load<R: Read>(stream: R) -> serde_json::Result<Self> {
+        serde_json::from_reader(stream)
+    }
+}
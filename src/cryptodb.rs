@@ -0,0 +1,218 @@
+//! Database of encrypted talkgroups learned on each system.
+
+use std::path::PathBuf;
+use std;
+
+use p25::voice::crypto::CryptoAlgorithm;
+
+use sites::SystemId;
+use store::JsonStore;
+use talkgroups::GroupCryptoMap;
+
+/// Encryption seen on a talkgroup.
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct GroupCrypto {
+    /// Talkgroup ID.
+    pub tg: u16,
+    /// Encryption algorithm ID.
+    pub alg: u8,
+    /// Key ID.
+    pub key: u16,
+    /// Time the talkgroup was last seen encrypted (Unix timestamp).
+    #[serde(rename = "lastSeen")]
+    pub last_seen: i64,
+}
+
+/// System and its encrypted talkgroups.
+#[derive(Serialize, Deserialize)]
+pub struct SystemCrypto {
+    /// Wide area communications network ID.
+    pub wacn: u32,
+    /// System ID within the network.
+    pub system: u16,
+    /// Talkgroups seen encrypted on the system.
+    pub groups: Vec<GroupCrypto>,
+}
+
+impl SystemCrypto {
+    /// Get the identity of the system.
+    pub fn id(&self) -> SystemId {
+        SystemId {
+            wacn: self.wacn,
+            system: self.system,
+        }
+    }
+}
+
+/// Encrypted talkgroups learned on all systems.
+#[derive(Default, Serialize, Deserialize)]
+pub struct CryptoDb {
+    /// Systems with encrypted talkgroups.
+    pub systems: Vec<SystemCrypto>,
+}
+
+impl CryptoDb {
+    /// Record that the given talkgroup on the given system was seen encrypted with the
+    /// given algorithm ID and key at the given time.
+    pub fn record(&mut self, id: SystemId, tg: u16, alg: u8, key: u16, now: i64) {
+        let pos = self.systems.iter().position(|s| s.id() == id);
+
+        let sys = match pos {
+            Some(idx) => &mut self.systems[idx],
+            None => {
+                self.systems.push(SystemCrypto {
+                    wacn: id.wacn,
+                    system: id.system,
+                    groups: vec![],
+                });
+
+                self.systems.last_mut().unwrap()
+            },
+        };
+
+        sys.groups.retain(|g| g.tg != tg);
+        sys.groups.push(GroupCrypto {
+            tg: tg,
+            alg: alg,
+            key: key,
+            last_seen: now,
+        });
+    }
+
+    /// Get the encrypted talkgroups of the given system.
+    pub fn groups(&self, id: SystemId) -> &[GroupCrypto] {
+        self.systems.iter()
+            .find(|s| s.id() == id)
+            .map_or(&[][..], |s| &s.groups[..])
+    }
+
+    /// Forget the given talkgroup, or all talkgroups if `None`, on the given system.
+    pub fn remove(&mut self, id: SystemId, tg: Option<u16>) {
+        for s in self.systems.iter_mut() {
+            if s.id() == id {
+                s.groups.retain(|g| tg.map_or(false, |tg| g.tg != tg));
+            }
+        }
+
+        self.systems.retain(|s| !s.groups.is_empty());
+    }
+
+    /// Forget talkgroups not seen encrypted within the given time (sec) before the given
+    /// time, returning whether any were forgotten.
+    pub fn expire(&mut self, now: i64, expiry: i64) -> bool {
+        let mut expired = false;
+
+        for s in self.systems.iter_mut() {
+            let len = s.groups.len();
+            s.groups.retain(|g| now - g.last_seen < expiry);
+            expired |= s.groups.len() != len;
+        }
+
+        self.systems.retain(|s| !s.groups.is_empty());
+
+        expired
+    }
+}
+
+/// Encrypted talkgroup database with an optional backing file, which forgets talkgroups
+/// after some time.
+pub struct CryptoStore {
+    /// Backing database.
+    store: JsonStore<CryptoDb>,
+    /// Time (sec) a talkgroup is remembered as encrypted after it was last seen so, or
+    /// zero to remember talkgroups forever.
+    expiry: i64,
+}
+
+impl CryptoStore {
+    /// Create a new `CryptoStore` backed by the given file, loading any previously saved
+    /// database, that forgets talkgroups after the given time (sec).
+    pub fn open(path: Option<PathBuf>, expiry: i64) -> std::io::Result<Self> {
+        Ok(CryptoStore {
+            store: try!(JsonStore::open(path, "encrypted talkgroup database")),
+            expiry: expiry,
+        })
+    }
+
+    /// Get the current database.
+    pub fn db(&self) -> &CryptoDb {
+        self.store.db()
+    }
+
+    /// Get the current database for modification.
+    pub fn db_mut(&mut self) -> &mut CryptoDb {
+        self.store.db_mut()
+    }
+
+    /// Get the unexpired encrypted talkgroups of the given system as of the given time.
+    pub fn load(&mut self, id: SystemId, now: i64) -> GroupCryptoMap {
+        self.expire(now);
+
+        self.store.db().groups(id).iter()
+            .map(|g| (g.tg, CryptoAlgorithm::from_bits(g.alg)))
+            .collect()
+    }
+
+    /// Forget expired talkgroups as of the given time.
+    fn expire(&mut self, now: i64) {
+        let expiry = self.expiry;
+
+        if expiry != 0 {
+            self.store.update(|db| db.expire(now, expiry));
+        }
+    }
+
+    /// Expire talkgroups and save the database if it has been modified and the save
+    /// interval has elapsed as of the given time.
+    pub fn flush(&mut self, now: i64) {
+        self.expire(now);
+        self.store.flush(now);
+    }
+
+    /// Expire talkgroups and save the database if it has been modified, regardless of
+    /// the save interval.
+    pub fn flush_now(&mut self, now: i64) {
+        self.expire(now);
+        self.store.flush_now(now);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_crypto_db() {
+        let mut db = CryptoDb::default();
+
+        let a = SystemId { wacn: 0xBEE00, system: 0x123 };
+        let b = SystemId { wacn: 0xBEE00, system: 0x456 };
+
+        assert!(db.groups(a).is_empty());
+
+        db.record(a, 10, 0x84, 1, 100);
+        db.record(a, 20, 0x84, 2, 110);
+        db.record(a, 10, 0x84, 3, 120);
+        db.record(b, 10, 0x84, 4, 130);
+
+        assert_eq!(db.systems.len(), 2);
+        assert_eq!(db.groups(a).len(), 2);
+        assert_eq!(db.groups(a)[1].tg, 10);
+        assert_eq!(db.groups(a)[1].key, 3);
+        assert_eq!(db.groups(a)[1].last_seen, 120);
+
+        assert!(!db.expire(200, 100));
+        assert!(db.expire(215, 100));
+        assert_eq!(db.groups(a).len(), 1);
+        assert_eq!(db.groups(a)[0].tg, 10);
+
+        db.remove(a, Some(20));
+        assert_eq!(db.groups(a).len(), 1);
+        db.remove(a, Some(10));
+        assert!(db.groups(a).is_empty());
+        assert_eq!(db.systems.len(), 1);
+
+        db.remove(b, None);
+        assert!(db.systems.is_empty());
+    }
+}
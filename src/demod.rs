@@ -98,6 +98,8 @@ impl DemodTask {
 
                 self.hub.send(HubEvent::UpdateSignalPower(power))
                     .expect("unable to send signal power");
+                self.chan.send(RecvEvent::SignalPower(power))
+                    .expect("unable to send signal power");
             });
 
             let mut baseband = pool.checkout().expect("unable to allocate baseband");
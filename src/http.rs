@@ -1,5 +1,6 @@
 //! HTTP response utilities.
 
+use std::borrow::Cow;
 use std::io::{Write, BufWriter};
 use std;
 
@@ -7,6 +8,8 @@ use chrono::UTC;
 use serde::Serialize;
 use serde_json;
 use uhttp_chunked_write::ChunkedWrite;
+use uhttp_json_api::HttpResult;
+use uhttp_request::{self, Header};
 use uhttp_response_header::HeaderLines;
 use uhttp_status::StatusCode;
 use uhttp_version::HttpVersion;
@@ -39,3 +42,90 @@ pub fn send_json<W: Write, S: Serialize>(mut s: W, msg: S) -> std::io::Result<()
     serde_json::to_writer(&mut body, &msg)
         .map_err(|_| std::io::ErrorKind::Other.into())
 }
+
+/// Iterate over the percent-decoded `key=value` pairs in the given query string.
+///
+/// A key without a value is yielded with an empty value. A pair with invalid encoding
+/// is yielded as an error.
+pub fn query_pairs<'a>(query: &'a str)
+    -> impl Iterator<Item = HttpResult<(Cow<'a, str>, Cow<'a, str>)>>
+{
+    query.split('&').filter(|p| !p.is_empty()).map(|p| {
+        let mut parts = p.splitn(2, '=');
+        let key = try!(percent_decode(parts.next().unwrap()));
+        let val = try!(percent_decode(parts.next().unwrap_or("")));
+
+        Ok((key, val))
+    })
+}
+
+/// Decode `%XX` escapes and `+` spaces in the given query component.
+fn percent_decode(s: &str) -> HttpResult<Cow<str>> {
+    if !s.contains(|c| c == '%' || c == '+') {
+        return Ok(Cow::Borrowed(s));
+    }
+
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+
+    while let Some(b) = iter.next() {
+        bytes.push(match b {
+            b'+' => b' ',
+            b'%' => {
+                let hex = [
+                    iter.next().ok_or(StatusCode::BadRequest)?,
+                    iter.next().ok_or(StatusCode::BadRequest)?,
+                ];
+
+                std::str::from_utf8(&hex).ok()
+                    .and_then(|h| u8::from_str_radix(h, 16).ok())
+                    .ok_or(StatusCode::BadRequest)?
+            },
+            b => b,
+        });
+    }
+
+    String::from_utf8(bytes).map(Cow::Owned).map_err(|_| StatusCode::BadRequest)
+}
+
+/// Find the value of the first header with the given (case-insensitive) name.
+pub fn find_header<'a, H>(headers: H, name: &str) -> HttpResult<Option<&'a str>>
+    where H: Iterator<Item = uhttp_request::Result<Header<'a>>>
+{
+    for h in headers {
+        let h = h.map_err(|_| StatusCode::BadRequest)?;
+
+        if !h.name.eq_ignore_ascii_case(name) {
+            continue;
+        }
+
+        return std::str::from_utf8(h.val)
+            .map(|v| Some(v.trim()))
+            .map_err(|_| StatusCode::BadRequest);
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_query_pairs() {
+        let pairs = query_pairs("events=a%2Cb&tg=&x+y=1+2&&k")
+            .collect::<HttpResult<Vec<_>>>().unwrap();
+
+        assert_eq!(pairs, vec![
+            (Cow::Borrowed("events"), Cow::Borrowed("a,b")),
+            (Cow::Borrowed("tg"), Cow::Borrowed("")),
+            (Cow::Borrowed("x y"), Cow::Borrowed("1 2")),
+            (Cow::Borrowed("k"), Cow::Borrowed("")),
+        ]);
+
+        assert_eq!(percent_decode("%C3%A9").unwrap(), "\u{e9}");
+        assert!(percent_decode("%2").is_err());
+        assert!(percent_decode("%zz").is_err());
+        assert!(percent_decode("%FF").is_err());
+    }
+}
@@ -1,19 +1,24 @@
 //! HTTP REST interface and event streaming.
 
+use std::collections::{HashMap, VecDeque};
 use std::convert::TryFrom;
 use std::io::{Write, ErrorKind};
 use std::net::{SocketAddr, TcpStream};
-use std::os::unix::io::{RawFd, FromRawFd, IntoRawFd};
+use std::os::unix::io::{RawFd, AsRawFd, FromRawFd, IntoRawFd};
+use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
 use std::sync::mpsc::{Sender, TryRecvError};
+use std::time::Duration;
 use std;
 
 use arrayvec::ArrayVec;
+use chrono::UTC;
+use fnv::FnvBuildHasher;
 use mio::tcp::TcpListener;
 use mio::unix::EventedFd;
 use mio::{Poll, PollOpt, Token, Event, Events, Ready};
 use mio_more::channel::Receiver;
 use p25::stats::{CodeStats, Stats};
-use p25::trunking::fields::{self, ChannelParamsMap};
+use p25::trunking::fields::{self, RegResponse, TalkGroup};
 use p25::trunking::tsbk::{self, TsbkFields, TsbkOpcode};
 use p25::voice::control::{self, LinkControlFields, LinkControlOpcode};
 use p25::voice::crypto::CryptoAlgorithm;
@@ -22,25 +27,59 @@ use serde_json;
 use uhttp_json_api::{HttpRequest, HttpResult};
 use uhttp_method::Method;
 use uhttp_response_header::HeaderLines;
-use uhttp_sse::SseMessage;
 use uhttp_status::StatusCode;
 use uhttp_uri::HttpResource;
 use uhttp_version::HttpVersion;
 
+use channels::ChannelTable;
+use cryptodb::CryptoStore;
 use http;
+use identity::RejectStats;
+use patch::{PatchMap, Regroup};
+use raw::{RawLog, RawPacket};
+use vendor::{self, VendorTsbk};
+use policy::ReceiverMode;
 use recv::RecvEvent;
-use talkgroups::GroupCryptoMap;
+use signaling;
+use sites::{SiteStore, SystemId};
+use stream::{EventFilter, HubMessage, MessageBuf, Subscriber};
+use talkgroups::{Filter, GroupCryptoMap, ScanLists, TalkgroupPattern};
+use tgstats::{StatsEvent, StatsStore};
+use units::{UnitCall, UnitTracker};
+use ws::{self, Frame, Opcode};
 
 /// Available routes.
 enum Route {
-    /// Subscribe to SSE stream.
-    Subscribe,
+    /// Subscribe to SSE stream with the given event filter.
+    Subscribe(EventFilter),
+    /// Open WebSocket for events (with the given filter) and commands.
+    WebSocket(EventFilter),
     /// Get/Set control channel frequency.
     CtlFreq,
-    /// Get current known encrypted talkgroups.
-    Encrypted,
+    /// Get current known and stored encrypted talkgroups, or clear the given stored
+    /// talkgroup (all talkgroups if `None`) on the current system.
+    Encrypted(Option<u16>),
     /// Reset stat counters.
     ResetStats,
+    /// Get snapshot of all receiver state.
+    Status,
+    /// Get channel identifier table.
+    Channels,
+    /// Get database of learned sites.
+    Sites,
+    /// Get activity of the given tracked radio unit, or all units if `None`.
+    Units(Option<u32>),
+    /// Get/Set held talkgroup.
+    Hold,
+    /// Follow a talkgroup immediately.
+    Follow,
+    /// Skip the current call.
+    Skip,
+    /// Get/Set active scan list.
+    ScanList,
+    /// Get talkgroup statistics, busiest first, limited to the given number of
+    /// talkgroups.
+    Talkgroups(Option<usize>),
 }
 
 impl<'a> TryFrom<HttpResource<'a>> for Route {
@@ -48,18 +87,38 @@ impl<'a> TryFrom<HttpResource<'a>> for Route {
 
     fn try_from(r: HttpResource<'a>) -> HttpResult<Self> {
         match r.path {
-            "/subscribe" => Ok(Route::Subscribe),
+            "/subscribe" => EventFilter::from_query(r.query).map(Route::Subscribe),
+            "/ws" => EventFilter::from_query(r.query).map(Route::WebSocket),
             "/ctlfreq" => Ok(Route::CtlFreq),
-            "/encrypted" => Ok(Route::Encrypted),
+            "/encrypted" => parse_tg(r.query).map(Route::Encrypted),
             "/stats/reset" => Ok(Route::ResetStats),
+            "/status" => Ok(Route::Status),
+            "/channels" => Ok(Route::Channels),
+            "/sites" => Ok(Route::Sites),
+            "/units" => parse_unit(r.query).map(Route::Units),
+            "/hold" => Ok(Route::Hold),
+            "/follow" => Ok(Route::Follow),
+            "/skip" => Ok(Route::Skip),
+            "/scanlist" => Ok(Route::ScanList),
+            "/talkgroups" => parse_limit(r.query).map(Route::Talkgroups),
             _ => Err(StatusCode::NotFound),
         }
     }
 }
 
+/// Set when the process has been asked to terminate.
+pub static SHUTDOWN: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// Interval (sec) between checks for shutdown and pending writes of stored state.
+const TICK_SECS: u64 = 1;
+
+/// Number of recent events kept for replaying to reconnecting subscribers.
+const HISTORY: usize = 256;
+
 const CONNS: usize = 1 << 31;
 const EVENTS: usize = 1 << 30;
 const REQUEST: usize = 1 << 29;
+const SOCKET: usize = 1 << 28;
 
 /// Allow 24 bits for file descriptors
 ///
@@ -81,6 +140,8 @@ pub enum HubToken {
     Events,
     /// Request stream with contained file descriptor.
     Request(RawFd),
+    /// WebSocket subscriber with contained file descriptor.
+    Socket(RawFd),
 }
 
 impl From<HubToken> for Token {
@@ -88,7 +149,8 @@ impl From<HubToken> for Token {
         Token(match tok {
             HubToken::Conns => CONNS,
             HubToken::Events => EVENTS,
-            HubToken::Request(fd) => REQUEST | fd as usize
+            HubToken::Request(fd) => REQUEST | fd as usize,
+            HubToken::Socket(fd) => SOCKET | fd as usize,
         })
     }
 }
@@ -99,6 +161,7 @@ impl From<Token> for HubToken {
             CONNS => HubToken::Conns,
             EVENTS => HubToken::Events,
             REQUEST => HubToken::Request(tok.0 as RawFd & FD_MASK),
+            SOCKET => HubToken::Socket(tok.0 as RawFd & FD_MASK),
             _ => panic!("unknown token"),
         }
     }
@@ -109,6 +172,11 @@ impl HubToken {
         assert!(fd & !FD_MASK == 0);
         HubToken::Request(fd)
     }
+
+    pub fn for_socket(fd: RawFd) -> Self {
+        assert!(fd & !FD_MASK == 0);
+        HubToken::Socket(fd)
+    }
 }
 
 /// Handles HTTP requests and broadcasts events to listening subscribers.
@@ -120,17 +188,30 @@ pub struct HubTask {
     /// Async event loop.
     events: Poll,
     /// Streams subscribed to receive events.
-    streamers: ArrayVec<[TcpStream; 4]>,
+    streamers: ArrayVec<[Subscriber; 4]>,
+    /// Recently streamed events, oldest first.
+    history: VecDeque<HubMessage>,
+    /// Sequence number for the next streamed event.
+    next_id: u64,
+    /// Lowest event ID from which all replayable events are still in the history.
+    history_start: u64,
     /// Channel for receiving events.
     chan: Receiver<HubEvent>,
     /// Channel for communication with RecvTask.
     recv: Sender<RecvEvent>,
+    /// File to log raw packets to, if any.
+    raw_log: Option<RawLog>,
 }
 
 impl HubTask {
     /// Create a new `HubTask` to communicate on the given channels and bind to the given
-    /// socket address.
-    pub fn new(chan: Receiver<HubEvent>, recv: Sender<RecvEvent>, addr: &SocketAddr)
+    /// socket address, with the given initial channel identifier table, site database,
+    /// encrypted talkgroup database, talkgroup statistics, unit tracker, scan lists with
+    /// the initially active one, and raw packet log.
+    pub fn new(chan: Receiver<HubEvent>, recv: Sender<RecvEvent>, addr: &SocketAddr,
+               channels: ChannelTable, sites: SiteStore, crypto: CryptoStore,
+               tgstats: StatsStore, units: UnitTracker, scanlists: ScanLists,
+               scanlist: Option<String>, raw_log: Option<RawLog>)
         -> std::io::Result<Self>
     {
         let socket = TcpListener::bind(addr)?;
@@ -141,27 +222,46 @@ impl HubTask {
         try!(events.register(&chan, HubToken::Events.into(), Ready::readable(),
             PollOpt::edge()));
 
+        // Event IDs continue past any from a previous run, as long as events average
+        // fewer than a million per second.
+        let first_id = UTC::now().timestamp() as u64 * 1_000_000;
+
         Ok(HubTask {
-            state: State::default(),
+            state: State::new(channels, sites, crypto, tgstats, units, scanlists,
+                              scanlist),
             socket: socket,
             events: events,
             streamers: ArrayVec::new(),
+            history: VecDeque::with_capacity(HISTORY),
+            next_id: first_id,
+            history_start: first_id,
             chan: chan,
             recv: recv,
+            raw_log: raw_log,
         })
     }
 
     /// Start handling HTTP requests and events, blocking the current thread.
+    ///
+    /// On shutdown, stored state is saved and the process exits.
     pub fn run(&mut self) {
         let mut events = Events::with_capacity(32);
 
         loop {
-            self.events.poll(&mut events, None)
+            self.events.poll(&mut events, Some(Duration::from_secs(TICK_SECS)))
                 .expect("unable to poll events");
 
             for event in events.iter() {
                 self.handle_poll(event);
             }
+
+            if SHUTDOWN.load(Ordering::SeqCst) {
+                info!("saving state before exit");
+                self.state.flush_now(UTC::now().timestamp());
+                std::process::exit(0);
+            }
+
+            self.state.flush(UTC::now().timestamp());
         }
     }
 
@@ -180,6 +280,7 @@ impl HubTask {
 
                 self.handle_stream(stream);
             },
+            HubToken::Socket(fd) => self.handle_socket(fd),
         }
     }
 
@@ -217,12 +318,51 @@ impl HubTask {
 
     /// Handle the given channel event.
     fn handle_event(&mut self, e: HubEvent) {
-        if let HubEvent::State(sm) = e {
-            self.state.update(sm);
+        let system = self.state.site.system_id();
+
+        self.state.handle(&e);
+        self.state.units.expire(UTC::now().timestamp());
+
+        match self.state.site.system_id() {
+            Some(id) if system != Some(id) => self.load_encrypted(id),
+            _ => {},
+        }
+
+        if let HubEvent::RawPacket(ref p) = e {
+            if let Some(ref mut log) = self.raw_log {
+                if let Err(err) = log.write(p) {
+                    warn!("unable to write raw packet log: {}", err);
+                }
+            }
+        }
+
+        let mut msgs = MessageBuf::default();
+        self.stream_event(&mut msgs, &e);
+
+        for mut m in msgs.into_messages() {
+            m.id = Some(self.next_id);
+            self.next_id += 1;
+
+            self.broadcast(&m);
+
+            if !m.replayable() {
+                continue;
+            }
+
+            if self.history.len() == HISTORY {
+                if let Some(id) = self.history.pop_front().and_then(|m| m.id) {
+                    self.history_start = id + 1;
+                }
+            }
+
+            self.history.push_back(m);
         }
+    }
 
+    /// Send the given message to all subscribers, dropping any that have disconnected.
+    fn broadcast(&mut self, m: &HubMessage) {
         // Holds streamers that are still alive.
-        let mut keep = ArrayVec::<[TcpStream; 4]>::new();
+        let mut keep = ArrayVec::<[Subscriber; 4]>::new();
 
         loop {
             let mut s = match self.streamers.pop() {
@@ -230,7 +370,7 @@ impl HubTask {
                 None => break,
             };
 
-            if let Ok(()) = self.stream_event(&mut s, &e) {
+            if let Ok(()) = s.send(m) {
                 keep.push(s);
             }
         }
@@ -238,6 +378,55 @@ impl HubTask {
         self.streamers = keep;
     }
 
+    /// Handle pending data from the WebSocket subscriber with the given file descriptor.
+    fn handle_socket(&mut self, fd: RawFd) {
+        let idx = match self.streamers.iter().position(|s| s.as_raw_fd() == fd) {
+            Some(idx) => idx,
+            None => return,
+        };
+
+        let ok = match self.streamers[idx].recv_frames() {
+            Ok(frames) => frames.into_iter().all(|f| self.handle_frame(idx, f).is_ok()),
+            Err(()) => false,
+        };
+
+        if !ok {
+            self.events.deregister(&EventedFd(&fd))
+                .expect("unable to deregister socket");
+            self.streamers.remove(idx);
+        }
+    }
+
+    /// Handle the given frame received from the WebSocket subscriber at the given index.
+    ///
+    /// Return `Err(())` if the subscriber should be disconnected.
+    fn handle_frame(&mut self, idx: usize, f: Frame) -> Result<(), ()> {
+        match f.opcode {
+            Opcode::Text => {
+                let cmd: SerdeCommand = match serde_json::from_slice(&f.payload) {
+                    Ok(cmd) => cmd,
+                    Err(e) => {
+                        warn!("invalid websocket command: {}", e);
+                        return Ok(());
+                    },
+                };
+
+                if !cmd.valid() {
+                    warn!("invalid websocket command parameters");
+                    return Ok(());
+                }
+
+                self.recv.send(cmd.into()).map_err(|_| ())
+            },
+            Opcode::Ping => self.streamers[idx].send_frame(Opcode::Pong, &f.payload),
+            Opcode::Close => {
+                self.streamers[idx].send_frame(Opcode::Close, &f.payload).ok();
+                Err(())
+            },
+            Opcode::Binary | Opcode::Pong | Opcode::Continuation => Ok(()),
+        }
+    }
+
     /// Handle the given HTTP connection.
     fn handle_stream(&mut self, mut s: TcpStream) {
         match self.handle_request(&mut s) {
@@ -257,14 +446,23 @@ impl HubTask {
         }
 
         match (method, route) {
-            (Method::Get, Route::Subscribe) => {
+            (Method::Get, Route::Subscribe(filter)) => {
+                let last = http::find_header(req.headers()?, "Last-Event-ID")?
+                    .and_then(|id| id.parse::<u64>().ok());
+
                 if let Ok(mut s) = req.into_stream().try_clone() {
                     // Check if streamer can be supported before sending response.
                     if self.streamers.is_full() {
                         return Err(StatusCode::TooManyRequests);
                     }
 
-                    if self.start_stream(&mut s).is_ok() {
+                    if self.start_stream(&mut s).is_err() {
+                        return Ok(());
+                    }
+
+                    let mut s = Subscriber::new(s, filter);
+
+                    if self.catch_up(&mut s, last).is_ok() {
                         // This is guaranteed to succeed due to the above check.
                         self.streamers.push(s);
                     }
@@ -274,6 +472,39 @@ impl HubTask {
                     Err(StatusCode::InternalServerError)
                 }
             },
+            (Method::Get, Route::WebSocket(filter)) => {
+                let accept = ws::handshake(req.headers()?)?;
+
+                if let Ok(mut s) = req.into_stream().try_clone() {
+                    if self.streamers.is_full() {
+                        return Err(StatusCode::TooManyRequests);
+                    }
+
+                    if self.start_websocket(&mut s, &accept).is_err() {
+                        return Ok(());
+                    }
+
+                    let mut s = Subscriber::websocket(s, filter);
+
+                    if self.catch_up(&mut s, None).is_err() {
+                        return Ok(());
+                    }
+
+                    let fd = s.as_raw_fd();
+
+                    // Level triggering allows each readable event to be handled with a
+                    // single blocking read.
+                    self.events.register(&EventedFd(&fd), HubToken::for_socket(fd).into(),
+                        Ready::readable(), PollOpt::level())
+                        .expect("unable to register socket");
+
+                    self.streamers.push(s);
+
+                    Ok(())
+                } else {
+                    Err(StatusCode::InternalServerError)
+                }
+            },
             (Method::Get, Route::CtlFreq) => {
                 http::send_json(req.into_stream(), SerdeCtlFreq {
                     ctlfreq: self.state.ctlfreq,
@@ -294,24 +525,156 @@ impl HubTask {
 
                 Ok(())
             },
-            (Method::Get, Route::Encrypted) => {
+            (Method::Get, Route::Encrypted(None)) => {
                 http::send_json(req.into_stream(), json!({
                     "encrypted": &self.state.encrypted,
+                    "systems": &self.state.crypto.db().systems,
                 })).ok();
 
                 Ok(())
             },
+            (Method::Delete, Route::Encrypted(tg)) => {
+                if self.recv.send(RecvEvent::ClearEncrypted(tg)).is_err() {
+                    return Err(StatusCode::InternalServerError);
+                }
+
+                self.state.clear_encrypted(tg);
+                // Save right away so the cleared talkgroups don't return after a restart.
+                self.state.crypto.flush_now(UTC::now().timestamp());
+                http::send_status(req.into_stream(), StatusCode::Ok).ok();
+
+                Ok(())
+            },
+            (Method::Get, Route::Hold) => {
+                http::send_json(req.into_stream(), json!({
+                    "tg": self.state.hold,
+                })).ok();
+
+                Ok(())
+            },
+            (Method::Put, Route::Hold) => {
+                let msg: SerdeHold = req.read_json()?;
+
+                if !valid_secs(msg.timeout) {
+                    return Err(StatusCode::BadRequest);
+                }
+
+                let event = RecvEvent::HoldTalkgroup(msg.tg, msg.timeout);
+
+                if self.recv.send(event).is_err() {
+                    return Err(StatusCode::InternalServerError);
+                }
+
+                http::send_status(req.into_stream(), StatusCode::Ok).ok();
+
+                Ok(())
+            },
+            (Method::Put, Route::Follow) => {
+                let msg: SerdeFollow = req.read_json()?;
+
+                if self.recv.send(RecvEvent::FollowTalkgroup(msg.tg)).is_err() {
+                    return Err(StatusCode::InternalServerError);
+                }
+
+                http::send_status(req.into_stream(), StatusCode::Ok).ok();
+
+                Ok(())
+            },
+            (Method::Put, Route::Skip) => {
+                let msg: SerdeSkip = req.read_json()?;
+
+                if !valid_secs(msg.lockout) {
+                    return Err(StatusCode::BadRequest);
+                }
+
+                if self.recv.send(RecvEvent::SkipCall(msg.lockout)).is_err() {
+                    return Err(StatusCode::InternalServerError);
+                }
+
+                http::send_status(req.into_stream(), StatusCode::Ok).ok();
+
+                Ok(())
+            },
+            (Method::Get, Route::ScanList) => {
+                http::send_json(req.into_stream(), json!({
+                    "active": self.state.scanlist,
+                    "lists": &self.state.scanlists,
+                })).ok();
+
+                Ok(())
+            },
+            (Method::Put, Route::ScanList) => {
+                let msg: SerdeScanList = req.read_json()?;
+
+                let list = match self.state.scanlists.get(&msg.name) {
+                    Some(l) => l.clone(),
+                    None => return Err(StatusCode::NotFound),
+                };
+
+                if self.recv.send(RecvEvent::SetScanList(list)).is_err() {
+                    return Err(StatusCode::InternalServerError);
+                }
+
+                http::send_status(req.into_stream(), StatusCode::Ok).ok();
+                self.handle_event(HubEvent::UpdateScanList(msg.name));
+
+                Ok(())
+            },
             (Method::Put, Route::ResetStats) => {
                 self.recv.send(RecvEvent::ResetStats)
                     .expect("unable to reset stats");
 
                 Ok(())
             },
+            (Method::Get, Route::Status) => {
+                http::send_json(req.into_stream(), self.state.status()).ok();
+
+                Ok(())
+            },
+            (Method::Get, Route::Sites) => {
+                http::send_json(req.into_stream(), self.state.sites.db()).ok();
+
+                Ok(())
+            },
+            (Method::Get, Route::Units(None)) => {
+                http::send_json(req.into_stream(), json!({
+                    "units": self.state.units.units(),
+                })).ok();
+
+                Ok(())
+            },
+            (Method::Get, Route::Units(Some(unit))) => {
+                let info = match self.state.units.get(unit) {
+                    Some(info) => info,
+                    None => return Err(StatusCode::NotFound),
+                };
+
+                http::send_json(req.into_stream(), json!({
+                    "unit": unit,
+                    "info": info,
+                })).ok();
+
+                Ok(())
+            },
+            (Method::Get, Route::Talkgroups(limit)) => {
+                http::send_json(req.into_stream(), json!({
+                    "talkgroups": self.state.talkgroup_report(limit),
+                })).ok();
+
+                Ok(())
+            },
+            (Method::Get, Route::Channels) => {
+                http::send_json(req.into_stream(), json!({
+                    "channels": self.state.channel_table(),
+                })).ok();
+
+                Ok(())
+            },
             (Method::Options, _) => {
                 let mut h = HeaderLines::new(req.into_stream());
 
                 http::send_head(&mut h, StatusCode::Ok).ok();
-                write!(h.line(), "Access-Control-Allow-Methods: GET, PUT").ok();
+                write!(h.line(), "Access-Control-Allow-Methods: GET, PUT, DELETE").ok();
                 write!(h.line(), "Access-Control-Allow-Headers: Content-Type").ok();
 
                 Ok(())
@@ -320,6 +683,22 @@ impl HubTask {
         }
     }
 
+    /// Pass the stored encrypted talkgroups of the given newly identified system on to
+    /// the receiver.
+    fn load_encrypted(&mut self, id: SystemId) {
+        let enc = self.state.crypto.load(id, UTC::now().timestamp());
+
+        if enc.is_empty() {
+            return;
+        }
+
+        info!("loaded {} encrypted talkgroups for system {:03X}", enc.len(), id.system);
+
+        self.state.encrypted.extend(enc.iter().map(|(&tg, &alg)| (tg, alg)));
+        self.recv.send(RecvEvent::LoadEncrypted(enc))
+            .expect("unable to send encrypted talkgroups");
+    }
+
     /// Send the initial streaming header to the given subscriber.
     fn start_stream(&self, s: &mut TcpStream) -> std::io::Result<()> {
         let mut h = HeaderLines::new(s);
@@ -330,64 +709,156 @@ impl HubTask {
         Ok(())
     }
 
-    fn stream_event(&mut self, s: &mut TcpStream, e: &HubEvent) -> Result<(), ()> {
+    /// Send the WebSocket handshake response with the given accept key.
+    fn start_websocket(&self, s: &mut TcpStream, accept: &str) -> std::io::Result<()> {
+        let mut h = HeaderLines::new(s);
+
+        try!(http::send_head(&mut h, StatusCode::SwitchingProtocols));
+        try!(write!(h.line(), "Upgrade: websocket"));
+        try!(write!(h.line(), "Connection: Upgrade"));
+        try!(write!(h.line(), "Sec-WebSocket-Accept: {}", accept));
+
+        Ok(())
+    }
+
+    /// Bring the given new subscriber up to date by replaying any events missed after
+    /// the given event ID and then sending a snapshot of the current state.
+    ///
+    /// If the history no longer covers the given ID, a `gap` event is sent in place of
+    /// the replay.
+    fn catch_up(&self, s: &mut Subscriber, last: Option<u64>) -> Result<(), ()> {
+        let mut msgs = MessageBuf::default();
+
+        if let Some(last) = last {
+            if self.history_start <= last.saturating_add(1) && last < self.next_id {
+                let missed = self.history.iter()
+                    .filter(|m| m.id.map_or(false, |id| id > last));
+
+                for m in missed {
+                    try!(s.send(m));
+                }
+            } else {
+                msgs.push("gap", json!({
+                    "lastEventId": last,
+                }));
+            }
+        }
+
+        msgs.push("snapshot", self.state.snapshot());
+
+        for m in msgs.messages() {
+            try!(s.send(m));
+        }
+
+        Ok(())
+    }
+
+    /// Collect the messages to stream for the given event.
+    fn stream_event(&self, s: &mut MessageBuf, e: &HubEvent) {
         use self::HubEvent::*;
         use self::StateEvent::*;
 
         match *e {
-            State(UpdateCtlFreq(f)) => SerdeEvent::new("ctlFreq", f).write(s),
-            State(UpdateChannelParams(_)) => Ok(()),
-            State(UpdateEncrypted(..)) =>
-                SerdeEvent::new("updateEncrypted", &self.state.encrypted).write(s),
-            UpdateCurFreq(f) => SerdeEvent::new("curFreq", f).write(s),
-            UpdateTalkGroup(tg) => SerdeEvent::new("talkGroup", tg).write(s),
-            UpdateSignalPower(p) => SerdeEvent::new("sigPower", p).write(s),
-            // If this event has been received, the TSBK is valid with a known opcode.
-            TrunkingControl(tsbk) => match tsbk.opcode().unwrap() {
-                TsbkOpcode::RfssStatusBroadcast => stream_rfss_status(s,
+            State(UpdateCtlFreq(f)) => s.push("ctlFreq", f),
+            State(UpdateChannelParams(_)) => {},
+            State(UpdateEncrypted(tg, _, _)) =>
+                s.push_group("updateEncrypted", tg, &self.state.encrypted),
+            UpdateCurFreq(f) => s.push("curFreq", f),
+            UpdateTalkGroup(tg) => s.push_group("talkGroup", tg, tg),
+            UpdateHold(tg) => s.push("hold", tg),
+            UpdateScanList(ref name) => s.push("scanList", name),
+            UpdateSchedule(ref sched) => s.push("schedule", serialize_schedule(sched)),
+            SkipCall(tg, lockout) => s.push("skip", json!({
+                "talkgroup": tg,
+                "lockout": lockout,
+            })),
+            UpdatePrivateCall(src, dest) => s.push("privateCall", json!({
+                "src": src,
+                "dest": dest,
+            })),
+            VendorControl(ref v) => self.stream_vendor(s, v),
+            RawPacket(ref p) => s.push("rawPacket", p),
+            Emergency(tg, unit, freq) => s.push_group("emergency", tg, json!({
+                "talkgroup": tg,
+                "unit": unit,
+                "freq": freq,
+            })),
+            UpdateMode(m) => s.push("receiverMode", m),
+            ControlFailover(from, to) => s.push("ctlFailover", json!({
+                "from": from,
+                "to": to,
+            })),
+            ControlLocked(f) => s.push("ctlLocked", f),
+            SiteRoam(from, to) => s.push("siteRoam", json!({
+                "from": from,
+                "to": to,
+            })),
+            UpdateSignalPower(p) => s.push("sigPower", p),
+            // If this event has been received, the TSBK is valid and standard, but the
+            // opcode may not be known.
+            TrunkingControl(tsbk) => match tsbk.opcode() {
+                Some(TsbkOpcode::RfssStatusBroadcast) => stream_rfss_status(s,
                     fields::RfssStatusBroadcast::new(tsbk.payload())),
-                TsbkOpcode::NetworkStatusBroadcast => stream_net_status(s,
+                Some(TsbkOpcode::NetworkStatusBroadcast) => stream_net_status(s,
                     fields::NetworkStatusBroadcast::new(tsbk.payload())),
-                TsbkOpcode::AltControlChannel => self.stream_alt_control(s,
+                Some(TsbkOpcode::AltControlChannel) => self.stream_alt_control(s,
                     fields::AltControlChannel::new(tsbk.payload())),
-                TsbkOpcode::AdjacentSite => self.stream_adjacent_site(s,
+                Some(TsbkOpcode::AdjacentSite) => self.stream_adjacent_site(s,
                     fields::AdjacentSite::new(tsbk.payload())),
-                TsbkOpcode::LocRegResponse => {
+                Some(TsbkOpcode::LocRegResponse) => {
                     let f = tsbk::LocRegResponse::new(tsbk);
 
-                    SerdeEvent::new("locReg", json!({
+                    s.push("locReg", json!({
                         "response": f.response(),
                         "rfss": f.rfss(),
                         "site": f.site(),
                         "unit": f.dest_unit(),
-                    })).write(s)
+                    }))
                 },
-                TsbkOpcode::UnitRegResponse => {
+                Some(TsbkOpcode::UnitRegResponse) => {
                     let f = tsbk::UnitRegResponse::new(tsbk);
 
-                    SerdeEvent::new("unitReg", json!({
+                    s.push("unitReg", json!({
                         "response": f.response(),
                         "system": f.system(),
                         "unitId": f.src_id(),
                         "unitAddr": f.src_addr(),
-                    })).write(s)
+                    }))
                 },
-                TsbkOpcode::UnitDeregAck => {
+                Some(TsbkOpcode::UnitDeregAck) => {
                     let f = tsbk::UnitDeregAck::new(tsbk);
 
-                    SerdeEvent::new("unitDereg", json!({
+                    s.push("unitDereg", json!({
                         "wacn": f.wacn(),
                         "system": f.system(),
                         "unit": f.src_unit(),
-                    })).write(s)
+                    }))
+                },
+                _ => {
+                    for e in signaling::decode(tsbk, &self.state.channels) {
+                        match e.talkgroup {
+                            Some(tg) => s.push_group(e.name, tg, e.payload),
+                            None => s.push(e.name, e.payload),
+                        }
+                    }
                 },
-                _ => Ok(()),
             },
             // If this event has been received, the LC has a known opcode.
             LinkControl(lc) => match lc.opcode().unwrap() {
-                LinkControlOpcode::GroupVoiceTraffic =>
-                    SerdeEvent::new("srcUnit",
-                        control::GroupVoiceTraffic::new(lc).src_unit()).write(s),
+                LinkControlOpcode::GroupVoiceTraffic => {
+                    let f = control::GroupVoiceTraffic::new(lc);
+
+                    match f.talkgroup() {
+                        TalkGroup::Other(tg) => s.push_group("srcUnit", tg, f.src_unit()),
+                        _ => s.push("srcUnit", f.src_unit()),
+                    }
+                },
+                LinkControlOpcode::UnitVoiceTraffic => {
+                    let f = control::UnitVoiceTraffic::new(lc);
+
+                    s.push("srcUnit", f.src_unit());
+                    s.push("destUnit", f.dest_unit());
+                },
                 LinkControlOpcode::RfssStatusBroadcast => stream_rfss_status(s,
                     fields::RfssStatusBroadcast::new(lc.payload())),
                 LinkControlOpcode::NetworkStatusBroadcast => stream_net_status(s,
@@ -396,49 +867,70 @@ impl HubTask {
                     fields::AdjacentSite::new(lc.payload())),
                 LinkControlOpcode::AltControlChannel => self.stream_alt_control(s,
                     fields::AltControlChannel::new(lc.payload())),
-                _ => Ok(()),
+                _ => {},
             },
-            UpdateStats(stats) =>
-                SerdeEvent::new("updateStats", serialize_stats(&stats)).write(s),
+            UpdateStats(stats, rejects) =>
+                s.push("updateStats", serialize_stats(&stats, &rejects)),
+            UpdateTalkgroupStats(_) => {},
         }
     }
 
-    fn stream_alt_control(&self, mut s: &mut TcpStream, f: fields::AltControlChannel)
-        -> Result<(), ()>
-    {
+    fn stream_vendor(&self, s: &mut MessageBuf, v: &VendorTsbk) {
+        match *v {
+            VendorTsbk::Regroup(Regroup::Add(sg, ref tgs)) =>
+                s.push_group("patch", sg, json!({
+                    "action": "add",
+                    "supergroup": sg,
+                    "members": tgs,
+                })),
+            VendorTsbk::Regroup(Regroup::Delete(sg, ref tgs)) =>
+                s.push_group("patch", sg, json!({
+                    "action": "delete",
+                    "supergroup": sg,
+                    "members": tgs,
+                })),
+            VendorTsbk::Regroup(_) => {},
+            VendorTsbk::BaseStationId(ref callsign, ch) => s.push("baseStationId", json!({
+                "callsign": callsign,
+                "freq": self.state.channels.rx_freq(ch),
+            })),
+            VendorTsbk::Unknown(mfg, opcode, ref payload) => s.push("vendorTsbk", json!({
+                "mfg": mfg,
+                "vendor": vendor::vendor_name(mfg),
+                "opcode": opcode,
+                "payload": vendor::hex(&payload[..]),
+            })),
+        }
+    }
+
+    fn stream_alt_control(&self, s: &mut MessageBuf, f: fields::AltControlChannel) {
         for &(ch, _) in f.alts().iter() {
-            let freq = match self.state.channels.lookup(ch.id()) {
-                Some(p) => p.rx_freq(ch.number()),
+            let freq = match self.state.channels.rx_freq(ch) {
+                Some(f) => f,
                 None => continue,
             };
 
-            try!(SerdeEvent::new("altControl", json!({
+            s.push("altControl", json!({
                 "rfss": f.rfss(),
                 "site": f.site(),
                 "freq": freq,
-            })).write(&mut s));
+            }));
         }
-
-        Ok(())
     }
 
-    fn stream_adjacent_site(&self, s: &mut TcpStream, f: fields::AdjacentSite)
-        -> Result<(), ()>
-    {
-        let ch = f.channel();
-
-        let freq = match self.state.channels.lookup(ch.id()) {
-            Some(p) => p.rx_freq(ch.number()),
-            None => return Ok(()),
+    fn stream_adjacent_site(&self, s: &mut MessageBuf, f: fields::AdjacentSite) {
+        let freq = match self.state.channels.rx_freq(f.channel()) {
+            Some(f) => f,
+            None => return,
         };
 
-        SerdeEvent::new("adjacentSite", json!({
+        s.push("adjacentSite", json!({
             "area": f.area(),
             "rfss": f.rfss(),
             "system": f.system(),
             "site": f.site(),
             "freq": freq,
-        })).write(s)
+        }))
     }
 }
 
@@ -451,14 +943,43 @@ pub enum HubEvent {
     UpdateCurFreq(u32),
     /// Current talkgroup has changed.
     UpdateTalkGroup(u16),
+    /// Now holding the given talkgroup, or no longer holding if `None`.
+    UpdateHold(Option<u16>),
+    /// Scan list with the given name was made active.
+    UpdateScanList(String),
+    /// Scheduled talkgroups in the active scan list came into or went out of effect.
+    UpdateSchedule(Vec<(TalkgroupPattern, bool)>),
+    /// Current call was skipped, with its talkgroup (if not a private call) and the
+    /// time (seconds) the talkgroup was locked out for, if any.
+    SkipCall(Option<u16>, Option<f32>),
+    /// Now following a private call from the first unit to the second.
+    UpdatePrivateCall(u32, u32),
+    /// Raw packet was received, if enabled.
+    RawPacket(RawPacket),
+    /// Manufacturer-specific trunking packet was received.
+    VendorControl(VendorTsbk),
+    /// Emergency call was started on the given talkgroup by the given unit, on the
+    /// given traffic channel frequency (Hz) if known.
+    Emergency(u16, u32, Option<u32>),
+    /// Receiver policy has changed state.
+    UpdateMode(ReceiverMode),
+    /// Control channel was lost at the first frequency, so moving to the second.
+    ControlFailover(u32, u32),
+    /// Control channel at the given frequency was verified as belonging to the
+    /// expected system.
+    ControlLocked(u32),
+    /// Roamed from the site with the first control channel to the site with the second.
+    SiteRoam(u32, u32),
     /// Power of received signal.
     UpdateSignalPower(f32),
     /// Trunking control packet was received.
     TrunkingControl(TsbkFields),
     /// Link control packet was received.
     LinkControl(LinkControlFields),
-    /// Updated stat counters.
-    UpdateStats(Stats),
+    /// Talkgroup activity for the long-running statistics was seen.
+    UpdateTalkgroupStats(StatsEvent),
+    /// Updated stat counters and counts of packets rejected as from other systems.
+    UpdateStats(Stats, RejectStats),
 }
 
 /// State update events.
@@ -468,41 +989,493 @@ pub enum StateEvent {
     UpdateCtlFreq(u32),
     /// Channel parameters have been modified.
     UpdateChannelParams(TsbkFields),
-    /// Encrypted talkgroup encountered.
-    UpdateEncrypted(u16, CryptoAlgorithm),
+    /// Encrypted talkgroup encountered, with its algorithm ID and key ID.
+    UpdateEncrypted(u16, u8, u16),
 }
 
 /// Holds a copy of certain state held in other tasks.
 pub struct State {
     /// Current control channel frequency.
     ctlfreq: u32,
+    /// Whether the current control channel has been verified.
+    locked: bool,
+    /// Current center frequency.
+    curfreq: u32,
+    /// Most recently selected talkgroup.
+    talkgroup: u16,
+    /// Currently held talkgroup, if any.
+    hold: Option<u16>,
+    /// Named talkgroup scan lists.
+    scanlists: ScanLists,
+    /// Name of the active scan list, if any.
+    scanlist: Option<String>,
+    /// Scheduled talkgroups in the active scan list and whether each is in effect.
+    schedule: Vec<(TalkgroupPattern, bool)>,
+    /// Time (Unix seconds) each locked out talkgroup is excluded until.
+    lockouts: HashMap<u16, i64, FnvBuildHasher>,
+    /// Source and destination units of the private call being followed, if any.
+    private: Option<(u32, u32)>,
+    /// Current receiver policy state.
+    mode: ReceiverMode,
+    /// Identity of the current site.
+    site: SiteInfo,
     /// Channel parameters for current site.
-    channels: ChannelParamsMap,
+    channels: ChannelTable,
+    /// Neighbors of the current site.
+    adjacent: Vec<NeighborSite>,
+    /// Alternate control channels of the current site.
+    alts: Vec<AltControl>,
     /// Known encrypted talkgroups.
     encrypted: GroupCryptoMap,
+    /// Current talkgroup patches.
+    patches: PatchMap,
+    /// Sites learned from all broadcasts.
+    sites: SiteStore,
+    /// Encrypted talkgroups learned on all systems.
+    crypto: CryptoStore,
+    /// Activity of radio units.
+    units: UnitTracker,
+    /// Long-running talkgroup activity statistics.
+    tgstats: StatsStore,
+    /// Most recent signal power (dBm).
+    power: Option<f32>,
+    /// Most recent stat counters.
+    stats: Option<(Stats, RejectStats)>,
 }
 
-impl Default for State {
-    fn default() -> Self {
+impl State {
+    /// Create a new `State` with the given initial channel identifier table, site
+    /// database, encrypted talkgroup database, talkgroup statistics, unit tracker, and
+    /// scan lists with the initially active one.
+    fn new(channels: ChannelTable, sites: SiteStore, crypto: CryptoStore,
+           tgstats: StatsStore, units: UnitTracker, scanlists: ScanLists,
+           scanlist: Option<String>)
+        -> Self
+    {
         State {
             ctlfreq: std::u32::MAX,
-            channels: ChannelParamsMap::default(),
+            locked: false,
+            curfreq: std::u32::MAX,
+            talkgroup: 0,
+            hold: None,
+            scanlists: scanlists,
+            scanlist: scanlist,
+            schedule: Vec::new(),
+            lockouts: HashMap::default(),
+            private: None,
+            mode: ReceiverMode::Control,
+            site: SiteInfo::default(),
+            channels: channels,
+            adjacent: Vec::new(),
+            alts: Vec::new(),
             encrypted: GroupCryptoMap::default(),
+            patches: PatchMap::default(),
+            sites: sites,
+            crypto: crypto,
+            units: units,
+            tgstats: tgstats,
+            power: None,
+            stats: None,
         }
     }
-}
 
-impl State {
-    /// Update the state based on the given event.
+    /// Save modified databases whose save intervals have elapsed as of the given time.
+    fn flush(&mut self, now: i64) {
+        self.sites.flush(now);
+        self.crypto.flush(now);
+        self.tgstats.flush(now);
+    }
+
+    /// Save all modified databases, regardless of their save intervals.
+    fn flush_now(&mut self, now: i64) {
+        self.sites.flush_now(now);
+        self.crypto.flush_now(now);
+        self.tgstats.flush_now(now);
+    }
+
+    /// Update the state based on the given hub event.
+    fn handle(&mut self, e: &HubEvent) {
+        use self::HubEvent::*;
+
+        match *e {
+            State(sm) => self.update(sm),
+            UpdateCurFreq(f) => self.curfreq = f,
+            UpdateTalkGroup(tg) => {
+                self.talkgroup = tg;
+                self.private = None;
+            },
+            UpdatePrivateCall(src, dest) => self.private = Some((src, dest)),
+            UpdateHold(tg) => self.hold = tg,
+            UpdateScanList(ref name) => self.scanlist = Some(name.clone()),
+            UpdateSchedule(ref sched) => self.schedule = sched.clone(),
+            SkipCall(tg, lockout) => {
+                let now = UTC::now().timestamp();
+
+                self.lockouts.retain(|_, &mut until| until > now);
+
+                if let (Some(tg), Some(t)) = (tg, lockout) {
+                    self.lockouts.insert(tg, now + t.ceil() as i64);
+                }
+            },
+            Emergency(..) => {},
+            VendorControl(VendorTsbk::Regroup(ref r)) => self.patches.update(r),
+            VendorControl(_) => {},
+            RawPacket(_) => {},
+            UpdateMode(m) => self.mode = m,
+            ControlFailover(..) => {},
+            ControlLocked(_) => self.locked = true,
+            SiteRoam(..) => {},
+            UpdateSignalPower(p) => self.power = Some(p),
+            UpdateStats(s, r) => self.stats = Some((s, r)),
+            UpdateTalkgroupStats(e) => self.tgstats.db_mut().apply(e),
+            TrunkingControl(tsbk) => match tsbk.opcode() {
+                Some(TsbkOpcode::RfssStatusBroadcast) =>
+                    self.update_rfss(&fields::RfssStatusBroadcast::new(tsbk.payload())),
+                Some(TsbkOpcode::NetworkStatusBroadcast) =>
+                    self.site.update_net(&fields::NetworkStatusBroadcast::new(tsbk.payload())),
+                Some(TsbkOpcode::AdjacentSite) =>
+                    self.update_adjacent(&fields::AdjacentSite::new(tsbk.payload())),
+                Some(TsbkOpcode::AltControlChannel) =>
+                    self.update_alts(&fields::AltControlChannel::new(tsbk.payload())),
+                _ => self.update_units(tsbk),
+            },
+            LinkControl(lc) => match lc.opcode().unwrap() {
+                LinkControlOpcode::RfssStatusBroadcast =>
+                    self.update_rfss(&fields::RfssStatusBroadcast::new(lc.payload())),
+                LinkControlOpcode::NetworkStatusBroadcast =>
+                    self.site.update_net(&fields::NetworkStatusBroadcast::new(lc.payload())),
+                LinkControlOpcode::AdjacentSite =>
+                    self.update_adjacent(&fields::AdjacentSite::new(lc.payload())),
+                LinkControlOpcode::AltControlChannel =>
+                    self.update_alts(&fields::AltControlChannel::new(lc.payload())),
+                LinkControlOpcode::GroupVoiceTraffic => {
+                    let f = control::GroupVoiceTraffic::new(lc);
+
+                    if let TalkGroup::Other(tg) = f.talkgroup() {
+                        self.units.record_call(f.src_unit(), UnitCall::Group(tg),
+                                               UTC::now().timestamp());
+                    }
+                },
+                LinkControlOpcode::UnitVoiceTraffic => {
+                    let f = control::UnitVoiceTraffic::new(lc);
+
+                    self.units.record_call(f.src_unit(), UnitCall::Unit(f.dest_unit()),
+                                           UTC::now().timestamp());
+                },
+                _ => {},
+            },
+        }
+    }
+
+    /// Record unit activity in the given trunking packet.
+    fn update_units(&mut self, tsbk: TsbkFields) {
+        let now = UTC::now().timestamp();
+
+        match tsbk.opcode() {
+            Some(TsbkOpcode::GroupVoiceGrant) => {
+                let f = tsbk::GroupVoiceGrant::new(tsbk);
+
+                if let TalkGroup::Other(tg) = f.talkgroup() {
+                    self.units.record_call(f.src_unit(), UnitCall::Group(tg), now);
+                }
+            },
+            Some(TsbkOpcode::UnitVoiceGrant) | Some(TsbkOpcode::UnitVoiceUpdate) => {
+                let f = tsbk::UnitVoiceGrant::new(tsbk);
+                self.units.record_call(f.src_unit(), UnitCall::Unit(f.dest_unit()), now);
+            },
+            // Only accepted registrations are recorded.
+            Some(TsbkOpcode::LocRegResponse) => {
+                let f = tsbk::LocRegResponse::new(tsbk);
+
+                if let RegResponse::Accept = f.response() {
+                    self.units.register(f.dest_unit(), now);
+                }
+            },
+            Some(TsbkOpcode::UnitRegResponse) => {
+                let f = tsbk::UnitRegResponse::new(tsbk);
+
+                if let RegResponse::Accept = f.response() {
+                    self.units.register(f.src_id(), now);
+                }
+            },
+            Some(TsbkOpcode::UnitDeregAck) =>
+                self.units.deregister(tsbk::UnitDeregAck::new(tsbk).src_unit(), now),
+            _ => if let Some((tg, unit)) = signaling::affiliation(tsbk) {
+                self.units.affiliate(unit, tg, now);
+            },
+        }
+    }
+
+    /// Record the identity of the current site in the given broadcast.
+    fn update_rfss(&mut self, f: &fields::RfssStatusBroadcast) {
+        self.site.update_rfss(f);
+
+        let id = match self.site.system_id() {
+            Some(id) => id.site(f.rfss(), f.site()),
+            None => return,
+        };
+
+        let freq = self.channels.rx_freq(f.channel());
+
+        let site = self.sites.db_mut().site(id, UTC::now().timestamp());
+
+        site.area = Some(f.area());
+        site.services = Some(f.services().into());
+
+        if freq.is_some() {
+            site.ctlfreq = freq;
+        }
+    }
+
+    /// Record the neighbor site in the given broadcast.
+    fn update_adjacent(&mut self, f: &fields::AdjacentSite) {
+        let freq = match self.channels.rx_freq(f.channel()) {
+            Some(freq) => freq,
+            None => return,
+        };
+
+        // Neighbors are assumed to be part of the same network.
+        if let Some(wacn) = self.site.wacn {
+            let id = SystemId {
+                wacn: wacn,
+                system: f.system(),
+            };
+
+            let site = self.sites.db_mut().site(id.site(f.rfss(), f.site()),
+                                                UTC::now().timestamp());
+
+            site.area = Some(f.area());
+            site.ctlfreq = Some(freq);
+            site.services = Some(f.services().into());
+        }
+
+        let site = NeighborSite {
+            area: f.area(),
+            system: f.system(),
+            rfss: f.rfss(),
+            site: f.site(),
+            freq: freq,
+        };
+
+        self.adjacent.retain(|s| {
+            (s.system, s.rfss, s.site) != (site.system, site.rfss, site.site)
+        });
+
+        self.adjacent.push(site);
+    }
+
+    /// Record the alternate control channels in the given broadcast.
+    fn update_alts(&mut self, f: &fields::AltControlChannel) {
+        for &(ch, _) in f.alts().iter() {
+            let freq = match self.channels.rx_freq(ch) {
+                Some(freq) => freq,
+                None => continue,
+            };
+
+            if let Some(id) = self.site.system_id() {
+                let now = UTC::now().timestamp();
+                self.sites.db_mut().site(id.site(f.rfss(), f.site()), now).add_alt(freq);
+            }
+
+            if self.alts.iter().any(|a| a.freq == freq) {
+                continue;
+            }
+
+            self.alts.push(AltControl {
+                rfss: f.rfss(),
+                site: f.site(),
+                freq: freq,
+            });
+        }
+    }
+
+    /// Update the state based on the given state event.
     fn update(&mut self, e: StateEvent) {
         use self::StateEvent::*;
 
         match e {
-            UpdateCtlFreq(f) => self.ctlfreq = f,
+            UpdateCtlFreq(f) => {
+                // Site information is relearned when moving to a different control
+                // channel.
+                if f != self.ctlfreq {
+                    self.channels.reset();
+                    self.site = SiteInfo::default();
+                    self.adjacent.clear();
+                    self.alts.clear();
+                    self.locked = false;
+                    self.patches.clear();
+                }
+
+                self.ctlfreq = f;
+            },
             UpdateChannelParams(tsbk) =>
                 self.channels.update(&fields::ChannelParamsUpdate::new(tsbk.payload())),
-            UpdateEncrypted(tg, alg) => { self.encrypted.insert(tg, alg); },
+            UpdateEncrypted(tg, alg, key) => {
+                self.encrypted.insert(tg, CryptoAlgorithm::from_bits(alg));
+
+                // Talkgroups seen before the system is identified aren't stored.
+                if let Some(id) = self.site.system_id() {
+                    self.crypto.db_mut().record(id, tg, alg, key,
+                                                UTC::now().timestamp());
+                }
+            },
+        }
+    }
+
+    /// Forget that the given talkgroup, or all talkgroups if `None`, is encrypted on
+    /// the current system.
+    fn clear_encrypted(&mut self, tg: Option<u16>) {
+        match tg {
+            Some(tg) => { self.encrypted.remove(&tg); },
+            None => self.encrypted.clear(),
         }
+
+        if let Some(id) = self.site.system_id() {
+            self.crypto.db_mut().remove(id, tg);
+        }
+    }
+
+    /// Create a snapshot of the state for new subscribers.
+    fn snapshot(&self) -> impl Serialize {
+        json!({
+            "ctlFreq": self.ctlfreq,
+            "curFreq": self.curfreq,
+            "talkGroup": self.talkgroup,
+            "hold": self.hold,
+            "scanList": self.scanlist,
+            "schedule": serialize_schedule(&self.schedule),
+            "lockouts": self.lockout_list(),
+            "site": &self.site,
+            "encrypted": &self.encrypted,
+            "patches": &self.patches,
+        })
+    }
+
+    /// Serialize the talkgroups that are still locked out.
+    fn lockout_list(&self) -> Vec<serde_json::Value> {
+        let now = UTC::now().timestamp();
+
+        self.lockouts.iter()
+            .filter(|&(_, &until)| until > now)
+            .map(|(&tg, &until)| json!({
+                "talkgroup": tg,
+                "until": until,
+            })).collect()
+    }
+
+    /// Serialize the channel identifier table.
+    fn channel_table(&self) -> impl Serialize {
+        self.channels.entries().map(|(id, p, src)| json!({
+            "id": id,
+            "base": p.base,
+            "spacing": p.spacing,
+            "offset": p.offset,
+            "bandwidth": p.bandwidth,
+            "source": src,
+        })).collect::<Vec<_>>()
+    }
+
+    /// Create a report of talkgroup statistics with the most active talkgroups first,
+    /// limited to the given number of talkgroups.
+    fn talkgroup_report(&self, limit: Option<usize>) -> Vec<serde_json::Value> {
+        let mut groups = self.tgstats.db().groups().iter().collect::<Vec<_>>();
+
+        groups.sort_by(|&(_, a), &(_, b)| {
+            b.airtime.cmp(&a.airtime).then(b.grants.cmp(&a.grants))
+        });
+
+        groups.iter().take(limit.unwrap_or(std::usize::MAX)).map(|&(tg, s)| json!({
+            "talkgroup": tg,
+            "grants": s.grants,
+            "calls": s.calls,
+            "airtime": s.airtime,
+            "lastActive": s.last_active,
+            "encryptedFraction": s.encrypted_fraction(),
+            "hourly": &s.hourly[..],
+        })).collect()
+    }
+
+    /// Create a snapshot of all known state.
+    fn status(&self) -> impl Serialize {
+        json!({
+            "ctlFreq": self.ctlfreq,
+            "ctlLocked": self.locked,
+            "curFreq": self.curfreq,
+            "talkGroup": self.talkgroup,
+            "privateCall": self.private.map(|(src, dest)| json!({
+                "src": src,
+                "dest": dest,
+            })),
+            "mode": self.mode,
+            "hold": self.hold,
+            "scanList": self.scanlist,
+            "schedule": serialize_schedule(&self.schedule),
+            "lockouts": self.lockout_list(),
+            "site": &self.site,
+            "channels": self.channel_table(),
+            "adjacentSites": &self.adjacent,
+            "altControl": &self.alts,
+            "encrypted": &self.encrypted,
+            "patches": &self.patches,
+            "sigPower": self.power,
+            "stats": self.stats.as_ref().map(|&(ref s, ref r)| serialize_stats(s, r)),
+        })
+    }
+}
+
+/// Neighbor site learned from an adjacent site broadcast.
+#[derive(Copy, Clone, Serialize)]
+struct NeighborSite {
+    area: u8,
+    system: u16,
+    rfss: u8,
+    site: u8,
+    freq: u32,
+}
+
+/// Alternate control channel learned from the current site.
+#[derive(Copy, Clone, Serialize)]
+struct AltControl {
+    rfss: u8,
+    site: u8,
+    freq: u32,
+}
+
+/// Identity of the current site, as learned from status broadcasts.
+#[derive(Default, Serialize)]
+struct SiteInfo {
+    area: Option<u8>,
+    wacn: Option<u32>,
+    system: Option<u16>,
+    rfss: Option<u8>,
+    site: Option<u8>,
+}
+
+impl SiteInfo {
+    /// Update the site identity from the given RFSS status.
+    fn update_rfss(&mut self, f: &fields::RfssStatusBroadcast) {
+        self.area = Some(f.area());
+        self.system = Some(f.system());
+        self.rfss = Some(f.rfss());
+        self.site = Some(f.site());
+    }
+
+    /// Get the identity of the current system, if known.
+    fn system_id(&self) -> Option<SystemId> {
+        match (self.wacn, self.system) {
+            (Some(wacn), Some(system)) => Some(SystemId {
+                wacn: wacn,
+                system: system,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Update the site identity from the given network status.
+    fn update_net(&mut self, f: &fields::NetworkStatusBroadcast) {
+        self.area = Some(f.area());
+        self.wacn = Some(f.wacn());
+        self.system = Some(f.system());
     }
 }
 
@@ -511,50 +1484,156 @@ struct SerdeCtlFreq {
     ctlfreq: u32,
 }
 
-#[derive(Serialize)]
-struct SerdeEvent<T: Serialize> {
-    event: &'static str,
-    payload: T,
+#[derive(Deserialize)]
+struct SerdeHold {
+    tg: Option<u16>,
+    #[serde(default)]
+    timeout: Option<f32>,
 }
 
-impl<T: Serialize> SerdeEvent<T> {
-    pub fn new(event: &'static str, payload: T) -> Self {
-        SerdeEvent {
-            event: event,
-            payload: payload,
+#[derive(Deserialize)]
+struct SerdeFollow {
+    tg: u16,
+}
+
+#[derive(Deserialize)]
+struct SerdeScanList {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct SerdeSkip {
+    #[serde(default)]
+    lockout: Option<f32>,
+}
+
+/// Commands accepted from WebSocket subscribers.
+#[derive(Deserialize)]
+#[serde(tag = "cmd")]
+enum SerdeCommand {
+    #[serde(rename = "setCtlFreq")]
+    SetCtlFreq { ctlfreq: u32 },
+    #[serde(rename = "holdTalkgroup")]
+    HoldTalkgroup {
+        tg: Option<u16>,
+        #[serde(default)]
+        timeout: Option<f32>,
+    },
+    #[serde(rename = "followTalkgroup")]
+    FollowTalkgroup { tg: u16 },
+    #[serde(rename = "skipCall")]
+    SkipCall {
+        #[serde(default)]
+        lockout: Option<f32>,
+    },
+    #[serde(rename = "resetStats")]
+    ResetStats,
+    #[serde(rename = "setFilter")]
+    SetFilter { filter: Filter },
+}
+
+impl SerdeCommand {
+    /// Check if the command's parameters are in range.
+    fn valid(&self) -> bool {
+        match *self {
+            SerdeCommand::HoldTalkgroup { timeout, .. } => valid_secs(timeout),
+            SerdeCommand::SkipCall { lockout } => valid_secs(lockout),
+            _ => true,
+        }
+    }
+}
+
+impl From<SerdeCommand> for RecvEvent {
+    fn from(cmd: SerdeCommand) -> Self {
+        match cmd {
+            SerdeCommand::SetCtlFreq { ctlfreq } => RecvEvent::SetControlFreq(ctlfreq),
+            SerdeCommand::HoldTalkgroup { tg, timeout } =>
+                RecvEvent::HoldTalkgroup(tg, timeout),
+            SerdeCommand::FollowTalkgroup { tg } => RecvEvent::FollowTalkgroup(tg),
+            SerdeCommand::SkipCall { lockout } => RecvEvent::SkipCall(lockout),
+            SerdeCommand::ResetStats => RecvEvent::ResetStats,
+            SerdeCommand::SetFilter { filter } => RecvEvent::SetFilter(filter),
         }
     }
+}
+
+/// Check if the given optional duration (seconds) is finite and non-negative.
+fn valid_secs(t: Option<f32>) -> bool {
+    t.map_or(true, |t| t.is_finite() && t >= 0.0)
+}
 
-    pub fn write<W: Write>(&self, stream: W) -> Result<(), ()> {
-        let mut msg = SseMessage::new(stream);
-        let mut data = msg.data().map_err(|_| ())?;
+/// Parse the optional `limit` parameter in the given request query string.
+fn parse_limit(query: Option<&str>) -> HttpResult<Option<usize>> {
+    let mut limit = None;
 
-        serde_json::to_writer(&mut data, self).map_err(|_| ())
+    for pair in http::query_pairs(query.unwrap_or("")) {
+        let (key, val) = pair?;
+
+        match &key[..] {
+            "limit" => limit = Some(val.parse().map_err(|_| StatusCode::BadRequest)?),
+            _ => return Err(StatusCode::BadRequest),
+        }
     }
+
+    Ok(limit)
+}
+
+/// Parse the optional `unit` parameter in the given request query string.
+fn parse_unit(query: Option<&str>) -> HttpResult<Option<u32>> {
+    let mut unit = None;
+
+    for pair in http::query_pairs(query.unwrap_or("")) {
+        let (key, val) = pair?;
+
+        match &key[..] {
+            "unit" => unit = Some(val.parse().map_err(|_| StatusCode::BadRequest)?),
+            _ => return Err(StatusCode::BadRequest),
+        }
+    }
+
+    Ok(unit)
+}
+
+fn parse_tg(query: Option<&str>) -> HttpResult<Option<u16>> {
+    let mut tg = None;
+
+    for pair in http::query_pairs(query.unwrap_or("")) {
+        let (key, val) = pair?;
+
+        match &key[..] {
+            "tg" => tg = Some(val.parse().map_err(|_| StatusCode::BadRequest)?),
+            _ => return Err(StatusCode::BadRequest),
+        }
+    }
+
+    Ok(tg)
 }
 
-fn stream_rfss_status(s: &mut TcpStream, f: fields::RfssStatusBroadcast)
-    -> Result<(), ()>
-{
-    SerdeEvent::new("rfssStatus", json!({
+fn serialize_schedule(sched: &[(TalkgroupPattern, bool)]) -> Vec<serde_json::Value> {
+    sched.iter().map(|&(tg, active)| json!({
+        "talkgroup": tg,
+        "active": active,
+    })).collect()
+}
+
+fn stream_rfss_status(s: &mut MessageBuf, f: fields::RfssStatusBroadcast) {
+    s.push("rfssStatus", json!({
         "area": f.area(),
         "system": f.system(),
         "rfss": f.rfss(),
         "site": f.site(),
-    })).write(s)
+    }))
 }
 
-fn stream_net_status(s: &mut TcpStream, f: fields::NetworkStatusBroadcast)
-    -> Result<(), ()>
-{
-    SerdeEvent::new("networkStatus", json!({
+fn stream_net_status(s: &mut MessageBuf, f: fields::NetworkStatusBroadcast) {
+    s.push("networkStatus", json!({
         "area": f.area(),
         "wacn": f.wacn(),
         "system": f.system(),
-    })).write(s)
+    }))
 }
 
-fn serialize_stats(s: &Stats) -> impl Serialize {
+fn serialize_stats(s: &Stats, r: &RejectStats) -> impl Serialize {
     json!({
         "bch": serialize_code_stats(&s.bch),
         "cyclic": serialize_code_stats(&s.cyclic),
@@ -568,6 +1647,10 @@ fn serialize_stats(s: &Stats) -> impl Serialize {
         "rsLong": serialize_code_stats(&s.rs_long),
         "viterbiDibit": serialize_code_stats(&s.viterbi_dibit),
         "viterbiTribit": serialize_code_stats(&s.viterbi_tribit),
+        "rejected": {
+            "nac": r.nac,
+            "system": r.system,
+        },
     })
 }
 
@@ -579,3 +1662,34 @@ fn serialize_code_stats(s: &CodeStats) -> impl Serialize {
         "fixedSymbols": s.fixed,
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_status_schedule() {
+        let mut state = State::new(
+            ChannelTable::new(vec![]),
+            SiteStore::open(None, "site database").unwrap(),
+            CryptoStore::open(None, 0).unwrap(),
+            StatsStore::open(None, "talkgroup statistics").unwrap(),
+            UnitTracker::new(0),
+            ScanLists::default(),
+            None);
+
+        let status = serde_json::to_value(state.status()).unwrap();
+        assert_eq!(status["schedule"], json!([]));
+
+        state.handle(&HubEvent::UpdateSchedule(vec![
+            (TalkgroupPattern::Single(10), true),
+            (TalkgroupPattern::Range(20, 29), false),
+        ]));
+
+        let status = serde_json::to_value(state.status()).unwrap();
+        assert_eq!(status["schedule"], json!([
+            {"talkgroup": 10, "active": true},
+            {"talkgroup": "20-29", "active": false}
+        ]));
+    }
+}
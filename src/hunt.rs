@@ -0,0 +1,249 @@
+//! Control channel search, loss detection, and failover.
+
+use config::SystemIdentity;
+
+/// Searches candidate control channels for one matching the expected system, and
+/// detects loss of the control channel once locked on.
+pub struct ControlHunt {
+    /// Candidate control channel frequencies (Hz) in hunt order.
+    cands: Vec<u32>,
+    /// Expected identity of the system.
+    identity: SystemIdentity,
+    /// Time (baseband samples) without a valid TSBK before the control channel is
+    /// considered lost, or zero to disable detection.
+    timeout: usize,
+    /// Time (baseband samples) to dwell on an unverified candidate.
+    dwell: usize,
+    /// Time (baseband samples) since the last valid TSBK or candidate change.
+    elapsed: usize,
+    /// Whether the current control channel has been verified.
+    locked: bool,
+    /// Verification progress for the current candidate.
+    verify: Verification,
+}
+
+/// Verification progress for a candidate control channel.
+#[derive(Copy, Clone, Default)]
+struct Verification {
+    /// Whether a valid TSBK has been received.
+    tsbk: bool,
+    /// Whether a matching NAC has been received.
+    nac: bool,
+    /// Whether a matching network status has been received.
+    network: bool,
+}
+
+impl ControlHunt {
+    /// Create a new `ControlHunt` with the given known control channel frequencies (Hz),
+    /// expected system identity, loss timeout, and candidate dwell time (both in
+    /// baseband samples).
+    pub fn new(known: Vec<u32>, identity: SystemIdentity, timeout: usize, dwell: usize)
+        -> Self
+    {
+        let mut h = ControlHunt {
+            cands: Vec::with_capacity(known.len()),
+            identity: identity,
+            timeout: timeout,
+            dwell: dwell,
+            elapsed: 0,
+            locked: false,
+            verify: Verification::default(),
+        };
+
+        for f in known {
+            h.add_candidate(f);
+        }
+
+        h
+    }
+
+    /// Add the given frequency (Hz) to the candidate control channels.
+    pub fn add_candidate(&mut self, freq: u32) {
+        if !self.cands.contains(&freq) {
+            debug!("adding control channel candidate {} Hz", freq);
+            self.cands.push(freq);
+        }
+    }
+
+    /// Get the candidate control channels.
+    pub fn candidates(&self) -> &[u32] {
+        &self.cands[..]
+    }
+
+    /// Check if the current control channel has been verified.
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Require the current control channel to be verified again, such as after moving
+    /// to a different channel.
+    pub fn unlock(&mut self) {
+        self.locked = false;
+        self.verify = Verification::default();
+        self.elapsed = 0;
+    }
+
+    /// Record that a valid TSBK was received, returning whether the control channel
+    /// became verified.
+    pub fn record_tsbk(&mut self) -> bool {
+        self.verify.tsbk = true;
+
+        if self.locked {
+            self.elapsed = 0;
+        }
+
+        self.update_lock()
+    }
+
+    /// Record a received NAC, returning whether the control channel became verified.
+    pub fn record_nac(&mut self, nac: u16) -> bool {
+        if self.identity.nac.map_or(true, |n| n == nac) {
+            self.verify.nac = true;
+        }
+
+        self.update_lock()
+    }
+
+    /// Record a received network status, returning whether the control channel became
+    /// verified.
+    pub fn record_network(&mut self, wacn: u32, system: u16) -> bool {
+        if self.identity.wacn.map_or(true, |w| w == wacn) &&
+           self.identity.system.map_or(true, |s| s == system)
+        {
+            self.verify.network = true;
+        }
+
+        self.update_lock()
+    }
+
+    /// Lock on to the current candidate if all verification has passed, returning
+    /// whether the control channel became verified.
+    fn update_lock(&mut self) -> bool {
+        if self.locked {
+            return false;
+        }
+
+        let v = self.verify;
+
+        let nac = v.nac || self.identity.nac.is_none();
+        let network = v.network ||
+            self.identity.wacn.is_none() && self.identity.system.is_none();
+
+        if !(v.tsbk && nac && network) {
+            return false;
+        }
+
+        self.locked = true;
+        self.elapsed = 0;
+
+        true
+    }
+
+    /// Restart loss detection, such as when moving back to the control channel.
+    pub fn reset(&mut self) {
+        self.elapsed = 0;
+    }
+
+    /// Record the given elapsed baseband samples on the control channel and return
+    /// whether the receiver should move to the next candidate, either because the
+    /// control channel was lost or the current candidate couldn't be verified.
+    pub fn record_elapsed(&mut self, samples: usize) -> bool {
+        let max = if self.locked { self.timeout } else { self.dwell };
+
+        if max == 0 {
+            return false;
+        }
+
+        self.elapsed += samples;
+        self.elapsed >= max
+    }
+
+    /// Choose the candidate to try after the given current control channel frequency
+    /// (Hz), or `None` if there are no other candidates.
+    pub fn next_candidate(&mut self, cur: u32) -> Option<u32> {
+        self.unlock();
+
+        let next = match self.cands.iter().position(|&f| f == cur) {
+            Some(idx) => (idx + 1) % self.cands.len(),
+            None => 0,
+        };
+
+        match self.cands.get(next) {
+            Some(&f) if f != cur => Some(f),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hunt() {
+        let mut h = ControlHunt::new(vec![100, 200, 100], SystemIdentity::default(),
+                                     10, 5);
+        assert_eq!(h.candidates(), &[100, 200]);
+
+        assert!(!h.locked());
+        assert!(!h.record_elapsed(4));
+        assert!(h.record_elapsed(1));
+        assert_eq!(h.next_candidate(100), Some(200));
+
+        assert!(h.record_tsbk());
+        assert!(h.locked());
+        assert!(!h.record_tsbk());
+        assert!(!h.record_elapsed(9));
+        h.record_tsbk();
+        assert!(!h.record_elapsed(9));
+        assert!(h.record_elapsed(1));
+
+        assert_eq!(h.next_candidate(200), Some(100));
+        assert!(!h.locked());
+
+        h.add_candidate(300);
+        h.add_candidate(200);
+        assert_eq!(h.candidates(), &[100, 200, 300]);
+        assert_eq!(h.next_candidate(200), Some(300));
+        assert_eq!(h.next_candidate(300), Some(100));
+        assert_eq!(h.next_candidate(400), Some(100));
+
+        let mut h = ControlHunt::new(vec![100], SystemIdentity::default(), 10, 5);
+        assert_eq!(h.next_candidate(100), None);
+
+        let mut h = ControlHunt::new(vec![], SystemIdentity::default(), 0, 0);
+        assert!(!h.record_elapsed(1000));
+        assert_eq!(h.next_candidate(100), None);
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut h = ControlHunt::new(vec![100, 200], SystemIdentity {
+            wacn: Some(0xBEE00),
+            system: Some(0x123),
+            nac: Some(0x456),
+        }, 10, 5);
+
+        assert!(!h.record_tsbk());
+        assert!(!h.record_nac(0x293));
+        assert!(!h.record_network(0xBEE00, 0x124));
+        assert!(!h.record_nac(0x456));
+        assert!(!h.locked());
+        assert!(h.record_network(0xBEE00, 0x123));
+        assert!(h.locked());
+
+        h.next_candidate(100);
+        assert!(!h.record_network(0xBEE00, 0x123));
+        assert!(!h.record_nac(0x456));
+        assert!(h.record_tsbk());
+
+        let mut h = ControlHunt::new(vec![100], SystemIdentity {
+            wacn: None,
+            system: None,
+            nac: Some(0x456),
+        }, 10, 5);
+
+        assert!(!h.record_tsbk());
+        assert!(h.record_nac(0x456));
+    }
+}
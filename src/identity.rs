@@ -0,0 +1,194 @@
+//! Filtering of packets from other systems.
+
+use config::SystemIdentity;
+
+/// NAC used by receivers to accept any NAC.
+const NAC_RECEIVE_ANY: u16 = 0xF7E;
+/// NAC used by repeaters to repeat any NAC.
+const NAC_REPEAT_ANY: u16 = 0xF7F;
+
+/// Counts of packets discarded for belonging to another system.
+#[derive(Copy, Clone, Default)]
+pub struct RejectStats {
+    /// Packets with a mismatched NAC.
+    pub nac: usize,
+    /// Packets with a mismatched WACN or system ID.
+    pub system: usize,
+}
+
+impl RejectStats {
+    /// Reset all counters to zero.
+    pub fn clear(&mut self) {
+        *self = RejectStats::default();
+    }
+}
+
+/// Discards packets whose NAC or system identity doesn't match the expected system,
+/// such as from co-channel interference.
+pub struct IdentityFilter {
+    /// Configured identity.
+    expected: SystemIdentity,
+    /// NAC learned from the control channel when none is configured.
+    learned: Option<u16>,
+    /// Whether the packet following the most recent NID should be processed.
+    pass: bool,
+    /// Whether the current channel last broadcast the identity of another system.
+    foreign: bool,
+    /// Rejection counters.
+    rejects: RejectStats,
+}
+
+impl IdentityFilter {
+    /// Create a new `IdentityFilter` expecting the given identity.
+    pub fn new(expected: SystemIdentity) -> Self {
+        IdentityFilter {
+            expected: expected,
+            learned: None,
+            pass: true,
+            foreign: false,
+            rejects: RejectStats::default(),
+        }
+    }
+
+    /// Get the expected NAC, if known.
+    pub fn nac(&self) -> Option<u16> {
+        self.expected.nac.or(self.learned)
+    }
+
+    /// Forget any learned NAC, such as when moving to a different control channel.
+    pub fn reset(&mut self) {
+        self.learned = None;
+        self.retune();
+    }
+
+    /// Forget the state of the current channel, such as when moving to a different
+    /// frequency.
+    pub fn retune(&mut self) {
+        self.pass = true;
+        self.foreign = false;
+    }
+
+    /// Learn the given NAC (received on a verified control channel) if none is
+    /// configured.
+    pub fn learn_nac(&mut self, nac: u16) {
+        if self.expected.nac.is_some() || self.learned.is_some() {
+            return;
+        }
+
+        if nac == NAC_RECEIVE_ANY || nac == NAC_REPEAT_ANY {
+            return;
+        }
+
+        debug!("learned NAC {:03X}", nac);
+        self.learned = Some(nac);
+    }
+
+    /// Check the NAC of a received NID, returning whether it and its following packet
+    /// should be processed.
+    pub fn check_nac(&mut self, nac: u16) -> bool {
+        self.pass = match self.nac() {
+            Some(n) => nac == n || nac == NAC_RECEIVE_ANY || nac == NAC_REPEAT_ANY,
+            None => true,
+        };
+
+        if !self.pass {
+            self.rejects.nac += 1;
+        }
+
+        self.pass
+    }
+
+    /// Check if the packet following the most recent NID should be processed.
+    pub fn passes(&self) -> bool {
+        self.pass
+    }
+
+    /// Check the given broadcast network identity, returning whether the packet carrying
+    /// it should be processed.
+    ///
+    /// The current channel is considered to belong to another system until it next
+    /// broadcasts a matching identity or the filter is reset.
+    pub fn check_system(&mut self, wacn: Option<u32>, system: u16) -> bool {
+        let wacn_ok = match (self.expected.wacn, wacn) {
+            (Some(a), Some(b)) => a == b,
+            _ => true,
+        };
+
+        let pass = wacn_ok && self.expected.system.map_or(true, |s| s == system);
+
+        if !pass {
+            self.rejects.system += 1;
+        }
+
+        self.foreign = !pass;
+
+        pass
+    }
+
+    /// Check if the current channel has broadcast the identity of another system, in
+    /// which case its packets shouldn't be processed.
+    pub fn foreign(&self) -> bool {
+        self.foreign
+    }
+
+    /// Get the rejection counters.
+    pub fn rejects(&self) -> RejectStats {
+        self.rejects
+    }
+
+    /// Reset the rejection counters.
+    pub fn clear_stats(&mut self) {
+        self.rejects.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_identity() {
+        let mut f = IdentityFilter::new(SystemIdentity::default());
+
+        assert!(f.check_nac(0x123));
+        f.learn_nac(0xF7E);
+        assert_eq!(f.nac(), None);
+        f.learn_nac(0x293);
+        f.learn_nac(0x123);
+        assert_eq!(f.nac(), Some(0x293));
+        assert!(!f.check_nac(0x123));
+        assert!(!f.passes());
+        assert!(f.check_nac(0xF7E));
+        assert!(f.passes());
+        assert!(f.check_system(Some(0xBEE00), 0x123));
+        assert_eq!(f.rejects().nac, 1);
+
+        f.reset();
+        assert_eq!(f.nac(), None);
+
+        let mut f = IdentityFilter::new(SystemIdentity {
+            wacn: Some(0xBEE00),
+            system: Some(0x123),
+            nac: Some(0x456),
+        });
+
+        f.learn_nac(0x293);
+        assert_eq!(f.nac(), Some(0x456));
+        assert!(f.check_nac(0x456));
+        assert!(f.check_system(None, 0x123));
+        assert!(!f.check_system(Some(0xBEE01), 0x123));
+        assert!(!f.check_system(Some(0xBEE00), 0x124));
+        assert_eq!(f.rejects().system, 2);
+        assert!(f.foreign());
+        assert!(f.check_system(Some(0xBEE00), 0x123));
+        assert!(!f.foreign());
+        assert!(!f.check_system(None, 0x124));
+        assert!(f.foreign());
+        f.retune();
+        assert!(!f.foreign());
+        assert_eq!(f.nac(), Some(0x456));
+
+        f.clear_stats();
+        assert_eq!(f.rejects().system, 0);
+    }
+}
@@ -8,6 +8,7 @@ extern crate serde_json;
 extern crate log;
 
 extern crate arrayvec;
+extern crate base64;
 extern crate chan;
 extern crate chrono;
 extern crate clap;
@@ -29,6 +30,7 @@ extern crate prctl;
 extern crate rtlsdr_iq;
 extern crate rtlsdr_mt;
 extern crate serde;
+extern crate sha1;
 extern crate slice_cast;
 extern crate slice_mip;
 extern crate static_decimate;
@@ -37,6 +39,7 @@ extern crate throttle;
 extern crate uhttp_chunked_write;
 extern crate uhttp_json_api;
 extern crate uhttp_method;
+extern crate uhttp_request;
 extern crate uhttp_response_header;
 extern crate uhttp_sse;
 extern crate uhttp_status;
@@ -45,33 +48,64 @@ extern crate uhttp_version;
 
 use std::fs::{File, OpenOptions};
 use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
 use std::sync::mpsc::channel;
 
-use clap::{Arg, App};
+use clap::{Arg, App, ArgMatches, SubCommand};
 use env_logger::{Builder, Env};
 use log::LevelFilter;
-use rtlsdr_mt::TunerGains;
+use rtlsdr_mt::{Controller, Reader, TunerGains};
 
 mod audio;
+mod channels;
+mod config;
 mod consts;
+mod cryptodb;
 mod demod;
 mod http;
 mod hub;
+mod hunt;
+mod identity;
+mod patch;
 mod policy;
+mod raw;
 mod recv;
 mod replay;
+mod roam;
+mod scan;
+mod schedule;
 mod sdr;
+mod signaling;
+mod sites;
+mod store;
+mod stream;
 mod talkgroups;
+mod tgstats;
+mod units;
+mod vendor;
+mod ws;
 
 use audio::{AudioOutput, AudioTask};
+use channels::ChannelTable;
+use config::Config;
 use consts::{BASEBAND_SAMPLE_RATE, SDR_SAMPLE_RATE};
+use cryptodb::CryptoStore;
 use demod::DemodTask;
 use hub::HubTask;
+use hunt::ControlHunt;
+use identity::IdentityFilter;
 use policy::ReceiverPolicy;
+use raw::RawLog;
 use recv::RecvTask;
-use replay::ReplayReceiver;
+use replay::{ReplayReceiver, SimTuner};
+use roam::{RoamParams, SiteRoamer};
+use scan::{ScanParams, ScanTask};
 use sdr::{ReadTask, ControlTask};
+use sites::SiteStore;
 use talkgroups::TalkgroupSelection;
+use tgstats::StatsStore;
+use units::UnitTracker;
 
 fn main() {
     let args = App::new("p25rx")
@@ -102,7 +136,8 @@ fn main() {
              .value_name("FILE"))
         .arg(Arg::with_name("freq")
              .short("f")
-             .help("frequency for initial control channel (Hz)")
+             .help("frequency for initial control channel (Hz), or comma-separated \
+                    candidates to hunt across")
              .value_name("FREQ"))
         .arg(Arg::with_name("device")
              .short("d")
@@ -114,6 +149,28 @@ fn main() {
              .help("HTTP socket bind address")
              .default_value("0.0.0.0:8025")
              .value_name("BIND"))
+        .arg(Arg::with_name("config")
+             .short("c")
+             .long("config")
+             .help("JSON configuration file")
+             .value_name("FILE"))
+        .arg(Arg::with_name("sites")
+             .long("sites")
+             .help("file to persist learned network sites in")
+             .value_name("FILE"))
+        .arg(Arg::with_name("encrypted")
+             .long("encrypted")
+             .help("file to persist learned encrypted talkgroups in")
+             .value_name("FILE"))
+        .arg(Arg::with_name("tgstats")
+             .long("tgstats")
+             .help("file to persist talkgroup activity statistics in")
+             .value_name("FILE"))
+        .arg(Arg::with_name("cryptoexpiry")
+             .long("crypto-expiry")
+             .help("retry encrypted talkgroups after this many days (0 to never retry)")
+             .default_value("7")
+             .value_name("DAYS"))
         .arg(Arg::with_name("nohop")
              .short("n")
              .long("nohop")
@@ -128,11 +185,105 @@ fn main() {
              .help("time (sec) to wait for voice message to begin")
              .default_value("2.0")
              .value_name("TIME"))
+        .arg(Arg::with_name("ctltimeout")
+             .long("ctl-timeout")
+             .help("time (sec) without trunking packets before control channel failover \
+                    (0 to disable)")
+             .default_value("10.0")
+             .value_name("TIME"))
+        .arg(Arg::with_name("dwell")
+             .long("hunt-dwell")
+             .help("time (sec) to wait for a candidate control channel to be verified")
+             .default_value("1.5")
+             .value_name("TIME"))
+        .arg(Arg::with_name("unitexpiry")
+             .long("unit-expiry")
+             .help("forget radio units after this many minutes of inactivity (0 to keep)")
+             .default_value("60")
+             .value_name("MINUTES"))
+        .arg(Arg::with_name("raw")
+             .long("raw")
+             .help("stream every trunking packet and link control word"))
+        .arg(Arg::with_name("rawlog")
+             .long("raw-log")
+             .help("also log raw packets to FILE (implies --raw)")
+             .value_name("FILE"))
+        .arg(Arg::with_name("activity")
+             .long("activity-weight")
+             .help("weight of long-running talkgroup activity in selection (0 to ignore)")
+             .default_value("0")
+             .value_name("WEIGHT"))
+        .arg(Arg::with_name("emergency")
+             .long("emergency-preempt")
+             .help("follow emergency calls immediately, even if filtered out"))
+        .arg(Arg::with_name("roam")
+             .long("roam")
+             .help("move to a stronger adjacent site when the current one is weak"))
+        .arg(Arg::with_name("roamthreshold")
+             .long("roam-threshold")
+             .help("signal power (dBm) below which the current site is considered weak")
+             .default_value("-20.0")
+             .value_name("POWER"))
+        .arg(Arg::with_name("roammargin")
+             .long("roam-margin")
+             .help("amount (dB) an adjacent site must be stronger to roam to it")
+             .default_value("6.0")
+             .value_name("POWER"))
+        .arg(Arg::with_name("roamprobe")
+             .long("roam-probe")
+             .help("time (sec) to measure each adjacent site")
+             .default_value("2.0")
+             .value_name("TIME"))
+        .arg(Arg::with_name("simulate")
+             .long("simulate")
+             .help("use baseband FILE as the signal at FREQ instead of an RTL-SDR \
+                    (may be repeated)")
+             .multiple(true)
+             .number_of_values(1)
+             .value_name("FREQ=FILE"))
         .arg(Arg::with_name("tgselect")
              .long("tgselect-timeout")
              .help("time (sec) to collect talkgroups before making a selection")
              .default_value("1.0")
              .value_name("TIME"))
+        .subcommand(SubCommand::with_name("scan")
+            .about("sweep a frequency range for P25 control channels")
+            .arg(Arg::with_name("start")
+                 .long("start")
+                 .help("first frequency to scan (Hz)")
+                 .required(true)
+                 .value_name("FREQ"))
+            .arg(Arg::with_name("stop")
+                 .long("stop")
+                 .help("last frequency to scan (Hz)")
+                 .required(true)
+                 .value_name("FREQ"))
+            .arg(Arg::with_name("step")
+                 .long("step")
+                 .help("channel spacing (Hz)")
+                 .default_value("12500")
+                 .value_name("FREQ"))
+            .arg(Arg::with_name("measure")
+                 .long("measure")
+                 .help("time (sec) to measure signal power at each frequency")
+                 .default_value("0.3")
+                 .value_name("TIME"))
+            .arg(Arg::with_name("sync")
+                 .long("sync")
+                 .help("time (sec) to attempt decoding at each promising frequency")
+                 .default_value("3.0")
+                 .value_name("TIME"))
+            .arg(Arg::with_name("threshold")
+                 .long("threshold")
+                 .help("minimum signal power (dBm) to attempt decoding")
+                 .default_value("-20.0")
+                 .value_name("POWER"))
+            .arg(Arg::with_name("format")
+                 .long("format")
+                 .help("output format")
+                 .possible_values(&["json", "csv"])
+                 .default_value("json")
+                 .value_name("FORMAT")))
         .get_matches();
 
     {
@@ -159,6 +310,11 @@ fn main() {
         ))
     };
 
+    if let Some(sub) = args.subcommand_matches("scan") {
+        run_scan(&args, sub);
+        return;
+    }
+
     if let Some(path) = args.value_of("replay") {
         let mut stream = File::open(path).expect("unable to open replay file");
         let mut recv = ReplayReceiver::new(audio_out());
@@ -168,51 +324,34 @@ fn main() {
         return;
     }
 
-    let ppm: i32 = args.value_of("ppm").unwrap().parse()
-        .expect("invalid ppm");
-
     let samples_file = args.value_of("write")
         .map(|path| File::create(path).expect("unable to open baseband file"));
 
-    let dev: u32 = match args.value_of("device").unwrap() {
-        "list" => {
-            for (idx, name) in rtlsdr_mt::devices().enumerate() {
-                println!("{}: {}", idx, name.to_str().unwrap());
-            }
-
-            return;
-        },
-        s => s.parse().expect("invalid device index"),
+    let sim: Vec<(u32, Vec<f32>)> = match args.values_of("simulate") {
+        Some(vals) => vals.map(load_simulated).collect(),
+        None => vec![],
     };
 
-    info!("opening RTL-SDR at index {}", dev);
-    let (mut control, reader) = rtlsdr_mt::open(dev)
-        .expect("unable to open rtlsdr");
-
-    match args.value_of("gain").expect("-g option is required") {
-        "list" => {
-            let mut gains = TunerGains::default();
-
-            for g in control.tuner_gains(&mut gains) {
-                println!("{}", g);
-            }
+    let sdr = if sim.is_empty() {
+        match open_sdr(&args) {
+            Some(sdr) => Some(sdr),
+            None => return,
+        }
+    } else {
+        info!("simulating tuner with {} recordings", sim.len());
+        None
+    };
 
-            println!("auto");
+    let hopping = !args.is_present("nohop");
 
-            return;
+    let config = match args.value_of("config") {
+        Some(path) => {
+            info!("loading configuration from {}", path);
+            Config::load(File::open(path).expect("unable to open config file"))
+                .expect("invalid config file")
         },
-        "auto" => {
-            info!("enabling hardware AGC");
-            control.enable_agc().expect("unable to enable agc");
-        },
-        s => {
-            let gain = s.parse().expect("invalid gain");
-            info!("setting hardware gain to {:.1} dB", gain as f32 / 10.0);
-            control.set_tuner_gain(gain).expect("unable to set gain");
-        },
-    }
-
-    let hopping = !args.is_present("nohop");
+        None => Config::default(),
+    };
 
     let pause = time_samples(args.value_of("pause").unwrap().parse()
         .expect("invalid pause timeout"));
@@ -220,57 +359,140 @@ fn main() {
         .expect("invalid watchdog timeout"));
     let tgselect = time_samples(args.value_of("tgselect").unwrap().parse()
         .expect("invalid tgselect timeout"));
+    let ctltimeout = time_samples(args.value_of("ctltimeout").unwrap().parse()
+        .expect("invalid control channel timeout"));
+    let dwell = time_samples(args.value_of("dwell").unwrap().parse()
+        .expect("invalid hunt dwell time"));
+
+    let roam = if args.is_present("roam") {
+        Some(RoamParams {
+            threshold: args.value_of("roamthreshold").unwrap().parse()
+                .expect("invalid roam threshold"),
+            margin: args.value_of("roammargin").unwrap().parse()
+                .expect("invalid roam margin"),
+            probe: time_samples(args.value_of("roamprobe").unwrap().parse()
+                .expect("invalid roam probe time")),
+        })
+    } else {
+        None
+    };
 
-    info!("setting frequency offset to {} PPM", ppm);
-    control.set_ppm(ppm).expect("unable to set ppm");
-    control.set_sample_rate(SDR_SAMPLE_RATE).expect("unable to set sample rate");
+    let mut ctlfreqs: Vec<u32> = match args.value_of("freq") {
+        Some(s) => s.split(',').map(|f| f.trim().parse().expect("invalid frequency"))
+            .collect(),
+        None => vec![],
+    };
 
-    let freq: u32 = args.value_of("freq").expect("-f option is required")
-        .parse().expect("invalid frequency");
+    ctlfreqs.extend(config.ctlfreqs.iter().cloned());
+
+    let freq = *ctlfreqs.first()
+        .expect("-f option or ctlfreqs in config file is required");
     info!("using control channel frequency {} Hz", freq);
 
     let addr = args.value_of("bind").unwrap().parse()
         .expect("invalid bind address");
 
+    let sites = SiteStore::open(args.value_of("sites").map(PathBuf::from),
+                                "site database")
+        .expect("unable to load site database");
+
+    let cryptoexpiry: i64 = args.value_of("cryptoexpiry").unwrap().parse()
+        .expect("invalid encrypted talkgroup expiry");
+    let crypto = CryptoStore::open(args.value_of("encrypted").map(PathBuf::from),
+                                   cryptoexpiry * 24 * 60 * 60)
+        .expect("unable to load encrypted talkgroup database");
+
+    let tgstats = StatsStore::open(args.value_of("tgstats").map(PathBuf::from),
+                                   "talkgroup statistics")
+        .expect("unable to load talkgroup statistics");
+
+    let raw_log = args.value_of("rawlog").map(|path| {
+        info!("logging raw packets to {}", path);
+        RawLog::new(File::create(path).expect("unable to open raw packet log"))
+    });
+    let raw = args.is_present("raw") || raw_log.is_some();
+
+    let unitexpiry: i64 = args.value_of("unitexpiry").unwrap().parse()
+        .expect("invalid unit expiry");
+    let units = UnitTracker::new(unitexpiry * 60);
+
     let (tx_ctl, rx_ctl) = channel();
     let (tx_recv, rx_recv) = channel();
-    let (tx_read, rx_read) = channel();
     let (tx_audio, rx_audio) = channel();
     let (tx_hub, rx_hub) = mio_more::channel::channel();
 
     let policy = ReceiverPolicy::new(tgselect, watchdog, pause);
-    let talkgroups = TalkgroupSelection::default();
+    let mut talkgroups = TalkgroupSelection::default();
+    talkgroups.set_units(config.private);
+    talkgroups.set_emergency_preempt(args.is_present("emergency"));
+    talkgroups.set_activity_weight(args.value_of("activity").unwrap().parse()
+        .expect("invalid activity weight"));
+    talkgroups.set_stats(tgstats.db().clone());
+
+    if let Some(ref name) = config.scanlist {
+        let list = config.scanlists.get(name).expect("unknown initial scan list");
+        talkgroups.set_scanlist(list);
+    }
 
     info!("starting HTTP server at http://{}", addr);
-    let mut hub = HubTask::new(rx_hub, tx_recv.clone(), &addr)
+    let mut hub = HubTask::new(rx_hub, tx_recv.clone(), &addr,
+        ChannelTable::new(config.channels.clone()), sites, crypto, tgstats, units,
+        config.scanlists, config.scanlist, raw_log)
         .expect("unable to start hub");
-    let mut control = ControlTask::new(control, rx_ctl);
-    let mut read = ReadTask::new(tx_read);
-    let mut demod = DemodTask::new(rx_read, tx_hub.clone(), tx_recv.clone());
+
+    let source = match sdr {
+        Some((control, reader)) => {
+            let (tx_read, rx_read) = channel();
+
+            Source::Sdr(ControlTask::new(control, rx_ctl), ReadTask::new(tx_read),
+                        reader, DemodTask::new(rx_read, tx_hub.clone(), tx_recv.clone()))
+        },
+        None => Source::Sim(SimTuner::new(sim, rx_ctl, tx_recv.clone())),
+    };
+
     let mut recv = RecvTask::new(rx_recv, tx_hub.clone(),
-        tx_ctl.clone(), tx_audio.clone(), freq, hopping, policy, talkgroups);
+        tx_ctl.clone(), tx_audio.clone(), freq, hopping, policy, talkgroups,
+        ChannelTable::new(config.channels.clone()),
+        ControlHunt::new(ctlfreqs, config.system, ctltimeout, dwell),
+        SiteRoamer::new(roam),
+        IdentityFilter::new(config.system), raw);
     let mut audio = AudioTask::new(audio_out(), rx_audio);
 
+    unsafe {
+        libc::signal(libc::SIGINT, handle_signal as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, handle_signal as libc::sighandler_t);
+    }
+
     crossbeam::scope(|scope| {
         scope.spawn(move || {
             prctl::set_name("hub").unwrap();
             hub.run();
         });
 
-        scope.spawn(move || {
-            prctl::set_name("controller").unwrap();
-            control.run()
-        });
-
-        scope.spawn(move || {
-            prctl::set_name("reader").unwrap();
-            read.run(reader);
-        });
-
-        scope.spawn(move || {
-            prctl::set_name("demod").unwrap();
-            demod.run();
-        });
+        match source {
+            Source::Sdr(mut control, mut read, reader, mut demod) => {
+                scope.spawn(move || {
+                    prctl::set_name("controller").unwrap();
+                    control.run()
+                });
+
+                scope.spawn(move || {
+                    prctl::set_name("reader").unwrap();
+                    read.run(reader);
+                });
+
+                scope.spawn(move || {
+                    prctl::set_name("demod").unwrap();
+                    demod.run();
+                });
+            },
+            Source::Sim(mut sim) => {
+                scope.spawn(move || {
+                    prctl::set_name("tuner").unwrap();
+                    sim.run();
+                });
+            },
+        }
 
         scope.spawn(move || {
             prctl::set_name("receiver").unwrap();
@@ -293,7 +515,149 @@ fn main() {
     });
 }
 
+/// Ask the hub to save state and exit on a termination signal.
+extern "C" fn handle_signal(_: libc::c_int) {
+    hub::SHUTDOWN.store(true, Ordering::SeqCst);
+}
+
 /// Convert the given seconds into an amount of baseband samples.
 fn time_samples(t: f32) -> usize {
     (t * BASEBAND_SAMPLE_RATE as f32) as usize
 }
+
+/// Sweep the band for control channels and print the results to stdout.
+fn run_scan(args: &ArgMatches, sub: &ArgMatches) {
+    let params = ScanParams {
+        start: sub.value_of("start").unwrap().parse().expect("invalid start frequency"),
+        stop: sub.value_of("stop").unwrap().parse().expect("invalid stop frequency"),
+        step: sub.value_of("step").unwrap().parse().expect("invalid step"),
+        measure: time_samples(sub.value_of("measure").unwrap().parse()
+            .expect("invalid measure time")),
+        sync: time_samples(sub.value_of("sync").unwrap().parse()
+            .expect("invalid sync time")),
+        threshold: sub.value_of("threshold").unwrap().parse()
+            .expect("invalid threshold"),
+    };
+
+    assert!(params.step > 0, "step must be nonzero");
+
+    let (control, reader) = match open_sdr(args) {
+        Some(sdr) => sdr,
+        None => return,
+    };
+
+    let (tx_ctl, rx_ctl) = channel();
+    let (tx_recv, rx_recv) = channel();
+    let (tx_read, rx_read) = channel();
+    let (tx_hub, rx_hub) = mio_more::channel::channel();
+
+    let mut control = ControlTask::new(control, rx_ctl);
+    let mut read = ReadTask::new(tx_read);
+    let mut demod = DemodTask::new(rx_read, tx_hub, tx_recv);
+    let mut scan = ScanTask::new(rx_recv, rx_hub, tx_ctl, params);
+    let format = sub.value_of("format").unwrap();
+
+    crossbeam::scope(|scope| {
+        scope.spawn(move || {
+            prctl::set_name("controller").unwrap();
+            control.run()
+        });
+
+        scope.spawn(move || {
+            prctl::set_name("reader").unwrap();
+            read.run(reader);
+        });
+
+        scope.spawn(move || {
+            prctl::set_name("demod").unwrap();
+            demod.run();
+        });
+
+        let found = scan.run();
+        info!("found {} control channels", found.len());
+
+        let stdout = std::io::stdout();
+
+        match format {
+            "csv" => scan::write_csv(stdout.lock(), &found[..]),
+            _ => scan::write_json(stdout.lock(), &found[..]),
+        }.expect("unable to write scan results");
+
+        // The SDR tasks never return, so exit directly.
+        std::process::exit(0);
+    });
+}
+
+/// Source of baseband samples.
+enum Source {
+    /// RTL-SDR hardware.
+    Sdr(ControlTask, ReadTask, Reader, DemodTask),
+    /// Recordings served by a simulated tuner.
+    Sim(SimTuner),
+}
+
+/// Open and configure the RTL-SDR, or return `None` if only listing options.
+fn open_sdr(args: &ArgMatches) -> Option<(Controller, Reader)> {
+    let ppm: i32 = args.value_of("ppm").unwrap().parse()
+        .expect("invalid ppm");
+
+    let dev: u32 = match args.value_of("device").unwrap() {
+        "list" => {
+            for (idx, name) in rtlsdr_mt::devices().enumerate() {
+                println!("{}: {}", idx, name.to_str().unwrap());
+            }
+
+            return None;
+        },
+        s => s.parse().expect("invalid device index"),
+    };
+
+    info!("opening RTL-SDR at index {}", dev);
+    let (mut control, reader) = rtlsdr_mt::open(dev)
+        .expect("unable to open rtlsdr");
+
+    match args.value_of("gain").expect("-g option is required") {
+        "list" => {
+            let mut gains = TunerGains::default();
+
+            for g in control.tuner_gains(&mut gains) {
+                println!("{}", g);
+            }
+
+            println!("auto");
+
+            return None;
+        },
+        "auto" => {
+            info!("enabling hardware AGC");
+            control.enable_agc().expect("unable to enable agc");
+        },
+        s => {
+            let gain = s.parse().expect("invalid gain");
+            info!("setting hardware gain to {:.1} dB", gain as f32 / 10.0);
+            control.set_tuner_gain(gain).expect("unable to set gain");
+        },
+    }
+
+    info!("setting frequency offset to {} PPM", ppm);
+    control.set_ppm(ppm).expect("unable to set ppm");
+    control.set_sample_rate(SDR_SAMPLE_RATE).expect("unable to set sample rate");
+
+    Some((control, reader))
+}
+
+/// Load the baseband recording given as `FREQ=FILE`.
+fn load_simulated(arg: &str) -> (u32, Vec<f32>) {
+    let mut parts = arg.splitn(2, '=');
+
+    let freq = parts.next().unwrap().parse().expect("invalid simulated frequency");
+    let path = parts.next().expect("simulated recording must be FREQ=FILE");
+
+    info!("simulating {} Hz from {}", freq, path);
+
+    let samples = replay::load_baseband(File::open(path)
+        .expect("unable to open simulated recording"))
+        .expect("unable to read simulated recording");
+
+    (freq, samples)
+}
@@ -0,0 +1,92 @@
+//! Talkgroup patches and dynamic regrouping.
+
+use std::collections::hash_map::HashMap;
+
+use fnv::FnvBuildHasher;
+use p25::trunking::fields::Channel;
+
+/// Patch or regroup message.
+#[derive(Clone)]
+pub enum Regroup {
+    /// Talkgroups were patched into the supergroup.
+    Add(u16, Vec<u16>),
+    /// Talkgroups were removed from the supergroup.
+    Delete(u16, Vec<u16>),
+    /// Voice channel was granted to the supergroup for a call from the given unit.
+    Grant(u16, Channel, u32),
+    /// Voice channels in use by the given supergroups.
+    Update(Vec<(Channel, u16)>),
+}
+
+/// Maps supergroups to their member talkgroups.
+#[derive(Default, Clone, Serialize)]
+pub struct PatchMap(HashMap<u16, Vec<u16>, FnvBuildHasher>);
+
+impl PatchMap {
+    /// Update the patches with the given regroup message.
+    pub fn update(&mut self, r: &Regroup) {
+        match *r {
+            Regroup::Add(sg, ref tgs) => {
+                let members = self.0.entry(sg).or_insert_with(Vec::new);
+
+                for &tg in tgs {
+                    if tg != sg && !members.contains(&tg) {
+                        members.push(tg);
+                    }
+                }
+            },
+            Regroup::Delete(sg, ref tgs) => {
+                let empty = match self.0.get_mut(&sg) {
+                    Some(members) => {
+                        members.retain(|tg| !tgs.contains(tg));
+                        members.is_empty()
+                    },
+                    None => false,
+                };
+
+                if empty {
+                    self.0.remove(&sg);
+                }
+            },
+            Regroup::Grant(..) | Regroup::Update(..) => {},
+        }
+    }
+
+    /// Get the talkgroups that hear a call on the given talkgroup: the talkgroup
+    /// itself followed by any patched members.
+    pub fn members(&self, tg: u16) -> Vec<u16> {
+        let mut tgs = vec![tg];
+
+        if let Some(m) = self.0.get(&tg) {
+            tgs.extend(m.iter().cloned());
+        }
+
+        tgs
+    }
+
+    /// Remove all patches.
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_patch_map() {
+        let mut p = PatchMap::default();
+        assert_eq!(p.members(100), vec![100]);
+
+        p.update(&Regroup::Add(100, vec![1, 2]));
+        p.update(&Regroup::Add(100, vec![2, 3, 100]));
+        assert_eq!(p.members(100), vec![100, 1, 2, 3]);
+
+        p.update(&Regroup::Delete(100, vec![1, 3]));
+        assert_eq!(p.members(100), vec![100, 2]);
+
+        p.update(&Regroup::Delete(100, vec![2]));
+        assert!(p.0.is_empty());
+    }
+}
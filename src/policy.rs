@@ -18,6 +18,20 @@ pub enum PolicyEvent {
     ChooseTalkgroup,
 }
 
+/// Summary of the receiver state for reporting.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize)]
+pub enum ReceiverMode {
+    /// On the control channel.
+    #[serde(rename = "control")]
+    Control,
+    /// On a traffic channel.
+    #[serde(rename = "traffic")]
+    Traffic,
+    /// Pausing after a call termination.
+    #[serde(rename = "paused")]
+    Paused,
+}
+
 /// Current state of receiver.
 #[derive(Copy, Clone)]
 enum ReceiverState {
@@ -67,6 +81,15 @@ impl ReceiverPolicy {
         }
     }
 
+    /// Get a summary of the current state.
+    pub fn mode(&self) -> ReceiverMode {
+        match self.state {
+            Control(..) => ReceiverMode::Control,
+            Traffic(..) => ReceiverMode::Traffic,
+            Paused(..) => ReceiverMode::Paused,
+        }
+    }
+
     /// Record a given elapsed amount of baseband samples.
     pub fn handle_elapsed(&mut self, samples: usize) -> Option<PolicyEvent> {
         // FIXME: non-lexical borrowing
@@ -0,0 +1,131 @@
+//! Raw trunking and link control packets for protocol analysis.
+
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std;
+
+use chrono::UTC;
+use p25::trunking::tsbk::TsbkFields;
+use p25::voice::control::LinkControlFields;
+use serde_json::{self, Value};
+
+use channels::ChannelTable;
+use signaling;
+use vendor;
+
+/// Type of raw packet.
+#[derive(Copy, Clone, Serialize)]
+pub enum RawKind {
+    /// Trunking signaling block.
+    #[serde(rename = "tsbk")]
+    Tsbk,
+    /// Link control word.
+    #[serde(rename = "lc")]
+    LinkControl,
+}
+
+/// Raw packet with whatever could be decoded from it.
+#[derive(Clone, Serialize)]
+pub struct RawPacket {
+    /// Packet type.
+    pub kind: RawKind,
+    /// Frequency the packet was received on (Hz).
+    pub freq: u32,
+    /// Manufacturer ID.
+    pub mfg: u8,
+    /// Opcode, which is vendor-specific if the manufacturer ID is nonzero.
+    pub opcode: u8,
+    /// Name of the opcode, if known.
+    pub name: Option<String>,
+    /// Opcode, manufacturer ID, and payload bytes in hex.
+    pub hex: String,
+    /// Events decoded from the packet, each with its type and payload.
+    pub events: Vec<Value>,
+}
+
+impl RawPacket {
+    /// Create a new `RawPacket` from the given trunking packet received at the given
+    /// frequency (Hz), using the given channel table to resolve channel frequencies.
+    pub fn tsbk(tsbk: &TsbkFields, freq: u32, channels: &ChannelTable) -> Self {
+        let name = tsbk.opcode().map(|o| format!("{:?}", o));
+
+        let mut p = RawPacket::new(RawKind::Tsbk, freq, tsbk.mfg(), tsbk.raw_opcode(),
+                                   name, tsbk.payload());
+
+        if tsbk.mfg() == 0 {
+            p.events = signaling::decode(*tsbk, channels)
+                .into_iter()
+                // Undecoded packets are already covered by the hex.
+                .filter(|e| e.name != "tsbk")
+                .map(|e| json!({
+                    "event": e.name,
+                    "payload": e.payload,
+                }))
+                .collect();
+        }
+
+        p
+    }
+
+    /// Create a new `RawPacket` from the given link control word received at the given
+    /// frequency (Hz).
+    pub fn lc(lc: &LinkControlFields, freq: u32) -> Self {
+        let name = lc.opcode().map(|o| format!("{:?}", o));
+
+        RawPacket::new(RawKind::LinkControl, freq, lc.mfg(), lc.raw_opcode(), name,
+                       lc.payload())
+    }
+
+    fn new(kind: RawKind, freq: u32, mfg: u8, opcode: u8, name: Option<String>,
+           payload: &[u8])
+        -> Self
+    {
+        let mut bytes = vec![opcode, mfg];
+        bytes.extend_from_slice(payload);
+
+        RawPacket {
+            kind: kind,
+            freq: freq,
+            mfg: mfg,
+            opcode: opcode,
+            // Standard opcode names don't apply to vendor packets.
+            name: if mfg == 0 {
+                name
+            } else {
+                vendor::vendor_name(mfg).map(|v| format!("{}:{:02X}", v, opcode))
+            },
+            hex: vendor::hex(&bytes[..]),
+            events: vec![],
+        }
+    }
+}
+
+/// Writes raw packets to a file as timestamped JSON lines.
+pub struct RawLog(LineWriter<File>);
+
+impl RawLog {
+    /// Create a new `RawLog` writing to the given file.
+    pub fn new(file: File) -> Self {
+        RawLog(LineWriter::new(file))
+    }
+
+    /// Write the given packet with the current time.
+    pub fn write(&mut self, p: &RawPacket) -> std::io::Result<()> {
+        let line = json!({
+            "time": UTC::now().to_rfc3339(),
+            "kind": p.kind,
+            "freq": p.freq,
+            "mfg": p.mfg,
+            "opcode": p.opcode,
+            "name": p.name,
+            "hex": p.hex,
+            "events": p.events,
+        });
+
+        try!(serde_json::to_writer(&mut self.0, &line)
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other,
+                                             "unable to serialize raw packet")));
+
+        writeln!(self.0, "")
+    }
+}
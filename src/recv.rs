@@ -3,30 +3,70 @@
 use std::sync::mpsc::{Sender, Receiver};
 use std;
 
+use chrono::UTC;
 use mio_more;
 use p25::message::receiver::MessageReceiver;
 use p25::stats::Stats;
-use p25::trunking::fields::{self, TalkGroup, ChannelParamsMap, Channel};
+use p25::nid::NetworkAccessCode;
+use p25::trunking::fields::{self, TalkGroup, Channel};
 use p25::trunking::tsbk::{self, TsbkOpcode, TsbkFields};
-use p25::voice::control::LinkControlFields;
+use p25::voice::control::{self, LinkControlFields};
 use p25::voice::crypto::CryptoAlgorithm;
 use pool::Checkout;
 use throttle::Throttler;
 
 use audio::AudioEvent;
+use channels::ChannelTable;
+use consts::BASEBAND_SAMPLE_RATE;
 use hub::{HubEvent, StateEvent};
-use policy::{ReceiverPolicy, PolicyEvent};
+use hunt::ControlHunt;
+use patch::Regroup;
+use identity::IdentityFilter;
+use policy::{ReceiverMode, ReceiverPolicy, PolicyEvent};
+use raw::RawPacket;
+use roam::{RoamAction, SiteRoamer};
+use schedule::LocalTime;
 use sdr::ControlTaskEvent;
-use talkgroups::TalkgroupSelection;
+use talkgroups::{Call, Filter, GroupCryptoMap, ScanList, TalkgroupSelection};
+use tgstats::StatsEvent;
+use vendor::{self, VendorTsbk};
 
 /// Messages for `RecvTask`.
 pub enum RecvEvent {
     /// Chunk of baseband samples.
     Baseband(Checkout<Vec<f32>>),
+    /// Power (dBm) of received signal.
+    SignalPower(f32),
     /// Change the control channel frequency.
     SetControlFreq(u32),
     /// Reset stat counters.
     ResetStats,
+    /// Only follow the given talkgroup, or resume normal selection if `None`, with an
+    /// optional time (seconds) after which the hold is released.
+    HoldTalkgroup(Option<u16>, Option<f32>),
+    /// Follow the given talkgroup on its traffic channel immediately.
+    FollowTalkgroup(u16),
+    /// Return to the control channel from the current call, optionally locking out its
+    /// talkgroup for the given time (seconds).
+    SkipCall(Option<f32>),
+    /// Replace the user talkgroup filter.
+    SetFilter(Filter),
+    /// Replace the talkgroup filter, priorities, and preemption with the given scan
+    /// list.
+    SetScanList(ScanList),
+    /// Mark the given talkgroups, previously learned on the current system, as
+    /// encrypted.
+    LoadEncrypted(GroupCryptoMap),
+    /// Forget that the given talkgroup, or all talkgroups if `None`, is encrypted.
+    ClearEncrypted(Option<u16>),
+}
+
+/// Status broadcasts that carry a system identity.
+enum SystemBroadcast {
+    /// Network status broadcast, carrying WACN and system ID.
+    Network,
+    /// RFSS status broadcast, carrying system ID.
+    Rfss,
 }
 
 /// Processes P25 baseband and performs the duties of a trunking receiver.
@@ -47,14 +87,24 @@ pub struct RecvTask {
     msg: MessageReceiver,
     /// Policy state machine.
     policy: ReceiverPolicy,
+    /// Policy state most recently reported to the hub.
+    mode: ReceiverMode,
     /// Talkgroup selection machinery.
     talkgroups: TalkgroupSelection,
     /// Channel mappings.
-    channels: ChannelParamsMap,
+    channels: ChannelTable,
+    /// Control channel failover.
+    hunt: ControlHunt,
+    /// Roaming between adjacent sites.
+    roam: SiteRoamer,
+    /// Rejection of packets from other systems.
+    identity: IdentityFilter,
+    /// Whether every trunking packet and link control word is sent to the hub.
+    raw: bool,
     /// Current center frequency (Hz).
     curfreq: u32,
-    /// Current talkgroup being monitored.
-    curgroup: u16,
+    /// Current call being monitored.
+    curcall: Call,
     /// Accumlated statistics.
     stats: Stats,
 }
@@ -68,7 +118,12 @@ impl RecvTask {
                ctlfreq: u32,
                hopping: bool,
                policy: ReceiverPolicy,
-               talkgroups: TalkgroupSelection)
+               talkgroups: TalkgroupSelection,
+               channels: ChannelTable,
+               hunt: ControlHunt,
+               roam: SiteRoamer,
+               identity: IdentityFilter,
+               raw: bool)
         -> Self
     {
         RecvTask {
@@ -79,11 +134,16 @@ impl RecvTask {
             ctlfreq: std::u32::MAX,
             hopping: hopping,
             msg: MessageReceiver::new(),
+            mode: policy.mode(),
             policy: policy,
             talkgroups: talkgroups,
-            channels: ChannelParamsMap::default(),
+            channels: channels,
+            hunt: hunt,
+            roam: roam,
+            identity: identity,
+            raw: raw,
             curfreq: std::u32::MAX,
-            curgroup: 0,
+            curcall: Call::default(),
             stats: Stats::default(),
         }.init(ctlfreq)
     }
@@ -100,11 +160,15 @@ impl RecvTask {
     fn set_control_freq(&mut self, freq: u32) {
         // Reinitialize channel parameters if moving to a different channel.
         if freq != self.ctlfreq {
-            self.channels = ChannelParamsMap::default();
+            self.channels.reset();
             self.talkgroups.clear_state();
+            self.hunt.unlock();
+            self.roam.reset();
+            self.identity.reset();
         }
 
         self.ctlfreq = freq;
+        self.hunt.add_candidate(freq);
 
         self.hub.send(HubEvent::State(StateEvent::UpdateCtlFreq(freq)))
             .expect("unable to send control frequency");
@@ -116,17 +180,75 @@ impl RecvTask {
         self.audio.send(AudioEvent::EndTransmission)
             .expect("unable to send end of transmission");
 
+        self.record_stats(StatsEvent::EndCall(UTC::now().timestamp()));
+
         // FIXME: non-lexical borrowing
         let freq = self.ctlfreq;
         self.set_freq(freq);
 
         self.policy.enter_control();
+        self.hunt.reset();
+    }
+
+    /// Move to the next candidate control channel after losing the current one or
+    /// failing to verify it.
+    fn hunt_control(&mut self) {
+        let from = self.ctlfreq;
+        let locked = self.hunt.locked();
+
+        let to = match self.hunt.next_candidate(from) {
+            Some(f) => f,
+            None => {
+                debug!("no other control channel candidates after {} Hz", from);
+                return;
+            },
+        };
+
+        if locked {
+            info!("control channel {} Hz lost, trying {} Hz", from, to);
+        } else {
+            debug!("no valid control channel at {} Hz, trying {} Hz", from, to);
+        }
+
+        self.hub.send(HubEvent::ControlFailover(from, to))
+            .expect("unable to send control failover");
+
+        self.set_control_freq(to);
+    }
+
+    /// Carry out the given roaming action.
+    fn handle_roam(&mut self, action: RoamAction) {
+        match action {
+            RoamAction::Probe(freq) => {
+                debug!("measuring neighbor control channel {} Hz", freq);
+                self.set_freq(freq);
+            },
+            RoamAction::Return => self.switch_control(),
+            RoamAction::Move(to) => {
+                let from = self.ctlfreq;
+                info!("roaming from control channel {} Hz to {} Hz", from, to);
+
+                self.hub.send(HubEvent::SiteRoam(from, to))
+                    .expect("unable to send site roam");
+
+                self.set_control_freq(to);
+            },
+        }
+    }
+
+    /// Notify that the current control channel has been verified.
+    fn handle_locked(&mut self) {
+        info!("locked on to control channel {} Hz", self.ctlfreq);
+
+        self.hub.send(HubEvent::ControlLocked(self.ctlfreq))
+            .expect("unable to send control lock");
     }
 
     /// Move to the given frequency (Hz).
     fn set_freq(&mut self, freq: u32) {
         debug!("moving to frequency {} Hz", freq);
         self.curfreq = freq;
+        self.identity.retune();
 
         self.hub.send(HubEvent::UpdateCurFreq(freq))
             .expect("unable to send current frequency");
@@ -139,11 +261,17 @@ impl RecvTask {
     /// Begin processing baseband samples, blocking the current thread.
     pub fn run<F: FnMut(&[f32])>(&mut self, mut cb: F) {
         let mut stats_notifier = Throttler::new(16);
+        let mut schedule_notifier = Throttler::new(256);
+
+        self.update_schedule();
 
         loop {
             match self.events.recv().expect("unable to receive baseband") {
                 RecvEvent::Baseband(samples) => {
-                    self.talkgroups.record_elapsed(samples.len());
+                    if self.talkgroups.record_elapsed(samples.len()) {
+                        self.hub.send(HubEvent::UpdateHold(None))
+                            .expect("unable to send hold");
+                    }
 
                     for &s in samples.iter() {
                         self.handle_sample(s);
@@ -154,16 +282,77 @@ impl RecvTask {
                     // FIXME: non-lexical borrowing
                     let event = self.policy.handle_elapsed(samples.len());
                     self.handle_policy(event);
+
+                    if self.policy.mode() == ReceiverMode::Control {
+                        if let Some(action) = self.roam.record_elapsed(samples.len()) {
+                            self.handle_roam(action);
+                        } else if !self.roam.probing() &&
+                            self.hunt.record_elapsed(samples.len())
+                        {
+                            self.hunt_control();
+                        }
+                    }
+                },
+                RecvEvent::SignalPower(p) => {
+                    // Only the control channel is assessed for roaming.
+                    if self.policy.mode() == ReceiverMode::Control {
+                        self.roam.record_power(p);
+                    }
                 },
                 RecvEvent::SetControlFreq(freq) => self.set_control_freq(freq),
-                RecvEvent::ResetStats => self.stats.clear(),
+                RecvEvent::ResetStats => {
+                    self.stats.clear();
+                    self.identity.clear_stats();
+                },
+                RecvEvent::HoldTalkgroup(tg, timeout) => self.hold_talkgroup(tg, timeout),
+                RecvEvent::FollowTalkgroup(tg) => self.follow_talkgroup(tg),
+                RecvEvent::SkipCall(lockout) => self.skip_call(lockout),
+                RecvEvent::SetFilter(f) => {
+                    self.talkgroups.set_filter(f);
+                    self.update_schedule();
+                },
+                RecvEvent::SetScanList(l) => {
+                    self.talkgroups.set_scanlist(&l);
+                    self.update_schedule();
+                },
+                RecvEvent::LoadEncrypted(enc) => self.talkgroups.load_encrypted(enc),
+                RecvEvent::ClearEncrypted(tg) => self.talkgroups.clear_encrypted(tg),
             }
 
+            self.update_mode();
+
             stats_notifier.throttle(|| {
-                self.hub.send(HubEvent::UpdateStats(self.stats))
+                self.hub.send(HubEvent::UpdateStats(self.stats, self.identity.rejects()))
                     .expect("unable to send stats");
             });
+
+            schedule_notifier.throttle(|| self.update_schedule());
+        }
+    }
+
+    /// Evaluate filter and scan list schedules at the current local time, notifying the
+    /// hub if any entry came into or went out of effect.
+    fn update_schedule(&mut self) {
+        if !self.talkgroups.update_schedule(LocalTime::now()) {
+            return;
         }
+
+        self.hub.send(HubEvent::UpdateSchedule(self.talkgroups.schedule_state()))
+            .expect("unable to send schedule state");
+    }
+
+    /// Notify the hub if the policy state has changed.
+    fn update_mode(&mut self) {
+        let mode = self.policy.mode();
+
+        if mode == self.mode {
+            return;
+        }
+
+        self.mode = mode;
+
+        self.hub.send(HubEvent::UpdateMode(mode))
+            .expect("unable to send receiver mode");
     }
 
     /// Handle the given policy event.
@@ -178,26 +367,93 @@ impl RecvTask {
         match event {
             Resync => self.msg.resync(),
             ReturnControl => self.switch_control(),
+            // Traffic channels aren't followed until the control channel is verified.
+            ChooseTalkgroup if !self.hunt.locked() || self.roam.probing() => {},
             ChooseTalkgroup => {
-                if let Some((tg, freq)) = self.talkgroups.select_idle() {
-                    self.select_talkgroup(tg, freq);
+                if let Some((call, freq)) = self.talkgroups.select_idle() {
+                    self.select_call(call, freq);
                 }
             },
         }
     }
 
-    /// Choose the given talkgroup as the next to monitor.
-    fn select_talkgroup(&mut self, tg: u16, freq: u32) {
+    /// Hold the given talkgroup, optionally for the given time (seconds).
+    fn hold_talkgroup(&mut self, tg: Option<u16>, timeout: Option<f32>) {
+        match (tg, timeout) {
+            (Some(tg), Some(t)) => self.talkgroups.set_hold_timeout(tg,
+                (t * BASEBAND_SAMPLE_RATE as f32) as usize),
+            _ => self.talkgroups.set_hold(tg),
+        }
+
+        // Leave a call to another talkgroup so the held one can be picked up.
+        if let Some(tg) = tg {
+            if self.policy.mode() != ReceiverMode::Control &&
+               self.curcall != Call::Group(tg)
+            {
+                self.switch_control();
+            }
+        }
+
+        self.hub.send(HubEvent::UpdateHold(self.talkgroups.hold()))
+            .expect("unable to send hold");
+    }
+
+    /// Jump to the traffic channel of the given talkgroup.
+    fn follow_talkgroup(&mut self, tg: u16) {
+        match self.talkgroups.follow(tg) {
+            Some((call, freq)) => self.select_call(call, freq),
+            None => warn!("no traffic channel known for talkgroup {}", tg),
+        }
+    }
+
+    /// Leave the current call, skipping its talkgroup until the call ends and optionally
+    /// locking it out for the given time (seconds).
+    fn skip_call(&mut self, lockout: Option<f32>) {
+        if self.policy.mode() == ReceiverMode::Control {
+            return;
+        }
+
+        self.switch_control();
+
+        let tg = match self.curcall {
+            Call::Group(tg) => Some(tg),
+            Call::Unit(..) => None,
+        };
+
+        if let Some(tg) = tg {
+            self.talkgroups.skip(tg);
+        }
+
+        // Only talkgroups can be locked out.
+        let lockout = match (tg, lockout) {
+            (Some(tg), Some(t)) => {
+                self.talkgroups.lockout(tg, (t * BASEBAND_SAMPLE_RATE as f32) as usize);
+                Some(t)
+            },
+            _ => None,
+        };
+
+        self.hub.send(HubEvent::SkipCall(tg, lockout)).expect("unable to send skip");
+    }
+
+    /// Choose the given call as the next to monitor.
+    fn select_call(&mut self, call: Call, freq: u32) {
         if !self.hopping {
             return;
         }
 
-        self.curgroup = tg;
+        self.curcall = call;
         self.set_freq(freq);
         self.policy.enter_traffic();
 
-        self.hub.send(HubEvent::UpdateTalkGroup(self.curgroup))
-            .expect("unable to send talkgroup");
+        if let Call::Group(tg) = call {
+            self.record_stats(StatsEvent::StartCall(tg, UTC::now().timestamp()));
+        }
+
+        self.hub.send(match call {
+            Call::Group(tg) => HubEvent::UpdateTalkGroup(tg),
+            Call::Unit(src, dest) => HubEvent::UpdatePrivateCall(src, dest),
+        }).expect("unable to send call");
     }
 
     /// Process the given baseband sample.
@@ -212,17 +468,46 @@ impl RecvTask {
         self.stats.merge(&mut self.msg);
 
         match event {
-            Error(e) => self.stats.record_err(e),
+            Error(e) => {
+                self.stats.record_err(e);
+
+                if self.policy.mode() == ReceiverMode::Control {
+                    self.roam.record_error();
+                }
+            },
             PacketNID(nid) => {
                 trace!("received NID {:?}", nid.data_unit);
 
+                let nac = nac_bits(nid.access_code);
+                let control = self.policy.mode() == ReceiverMode::Control &&
+                    !self.roam.probing();
+
+                if control && self.hunt.record_nac(nac) {
+                    self.handle_locked();
+                }
+
+                if control && self.hunt.locked() {
+                    self.identity.learn_nac(nac);
+                }
+
+                if !self.identity.check_nac(nac) {
+                    trace!("rejected NID with NAC {:03X}", nac);
+                    return;
+                }
+
                 // FIXME: non-lexical borrowing
                 let event = self.policy.handle_nid(nid);
                 self.handle_policy(event);
             },
-            VoiceHeader(head) => self.handle_crypto(head.crypto_alg()),
+            // Packets following a rejected NID belong to another system.
+            _ if !self.identity.passes() => {},
+            // Voice on a channel broadcasting another system's identity is also foreign.
+            VoiceHeader(_) | CryptoControl(_) | VoiceFrame(_)
+                if self.identity.foreign() => {},
+            VoiceHeader(head) =>
+                self.handle_crypto(head.crypto_alg_id(), head.crypto_key()),
             LinkControl(lc) => self.handle_lc(lc),
-            CryptoControl(cc) => self.handle_crypto(cc.alg()),
+            CryptoControl(cc) => self.handle_crypto(cc.alg_id(), cc.key()),
             LowSpeedDataFragment(_) => {},
             VoiceFrame(vf) => {
                 self.audio.send(AudioEvent::VoiceFrame(vf))
@@ -235,33 +520,115 @@ impl RecvTask {
 
     /// Process the given trunking packet.
     fn handle_tsbk(&mut self, tsbk: TsbkFields) {
+        if !tsbk.crc_valid() {
+            return;
+        }
+
+        if self.raw {
+            let p = RawPacket::tsbk(&tsbk, self.curfreq, &self.channels);
+
+            self.hub.send(HubEvent::RawPacket(p))
+                .expect("unable to send raw packet");
+        }
+
         if tsbk.mfg() != 0 {
+            self.handle_vendor_tsbk(tsbk);
             return;
         }
 
-        if !tsbk.crc_valid() {
+        self.roam.record_packet();
+
+        // Packets from a neighbor being measured aren't otherwise processed.
+        if self.roam.probing() {
             return;
         }
 
-        let opcode = match tsbk.opcode() {
-            Some(o) => o,
-            None => return,
+        if self.hunt.record_tsbk() {
+            self.handle_locked();
+        }
+
+        trace!("received TSBK with opcode {:02X}", tsbk.raw_opcode());
+
+        let kind = match tsbk.opcode() {
+            Some(TsbkOpcode::NetworkStatusBroadcast) => Some(SystemBroadcast::Network),
+            Some(TsbkOpcode::RfssStatusBroadcast) => Some(SystemBroadcast::Rfss),
+            _ => None,
         };
 
-        trace!("received TSBK with opcode {:?}", opcode);
+        if !self.check_system(kind, tsbk.payload()) {
+            return;
+        }
 
+        // Packets with unknown opcodes are still decoded by the hub.
         self.hub.send(HubEvent::TrunkingControl(tsbk))
             .expect("unable to send trunking control");
 
+        let opcode = match tsbk.opcode() {
+            Some(o) => o,
+            None => return,
+        };
+
         match opcode {
             TsbkOpcode::GroupVoiceGrant => {
                 let grant = tsbk::GroupVoiceGrant::new(tsbk);
+                let emergency = grant.opts().emergency();
+                let freq = self.channels.rx_freq(grant.channel());
+
+                if let TalkGroup::Other(tg) = grant.talkgroup() {
+                    self.record_stats(StatsEvent::Grant(tg, UTC::now().timestamp()));
+                }
+
+                self.record_emergency(grant.talkgroup(), grant.src_unit(), emergency,
+                                      freq);
                 self.add_talkgroup(grant.talkgroup(), grant.channel());
+
+                // Emergency calls are followed without waiting for talkgroup selection.
+                if emergency && self.talkgroups.emergency_preempt() &&
+                    self.hunt.locked() && !self.roam.probing()
+                {
+                    if let TalkGroup::Other(tg) = grant.talkgroup() {
+                        if let Some((call, freq)) = self.talkgroups.select_emergency(tg) {
+                            self.select_call(call, freq);
+                        }
+                    }
+                }
+            },
+            // Grants and grant updates share the same layout.
+            TsbkOpcode::UnitVoiceGrant | TsbkOpcode::UnitVoiceUpdate => {
+                let grant = tsbk::UnitVoiceGrant::new(tsbk);
+                self.add_private(grant.src_unit(), grant.dest_unit(), grant.channel());
             },
             TsbkOpcode::GroupVoiceUpdate => {
                 self.handle_traffic_updates(
                     &fields::GroupTrafficUpdate::new(tsbk.payload()));
             },
+            TsbkOpcode::NetworkStatusBroadcast => {
+                let f = fields::NetworkStatusBroadcast::new(tsbk.payload());
+
+                if self.hunt.record_network(f.wacn(), f.system()) {
+                    self.handle_locked();
+                }
+            },
+            TsbkOpcode::RfssStatusBroadcast => {
+                let f = fields::RfssStatusBroadcast::new(tsbk.payload());
+                self.roam.record_system(f.system());
+            },
+            TsbkOpcode::AdjacentSite => {
+                let f = fields::AdjacentSite::new(tsbk.payload());
+
+                if let Some(freq) = self.channels.rx_freq(f.channel()) {
+                    self.roam.add_neighbor(f.system(), f.rfss(), f.site(), freq);
+                }
+            },
+            TsbkOpcode::AltControlChannel => {
+                let alts = fields::AltControlChannel::new(tsbk.payload());
+
+                for &(ch, _) in alts.alts().iter() {
+                    if let Some(freq) = self.channels.rx_freq(ch) {
+                        self.hunt.add_candidate(freq);
+                    }
+                }
+            },
             TsbkOpcode::ChannelParamsUpdate => {
                 let dec = fields::ChannelParamsUpdate::new(tsbk.payload());
                 self.channels.update(&dec);
@@ -273,10 +640,43 @@ impl RecvTask {
         }
     }
 
+    /// Process the given manufacturer-specific trunking packet.
+    fn handle_vendor_tsbk(&mut self, tsbk: TsbkFields) {
+        // Vendor packets don't identify their system, so they're only trusted from a
+        // verified control channel.
+        if self.roam.probing() || self.identity.foreign() || !self.hunt.locked() {
+            return;
+        }
+
+        let v = vendor::decode(&tsbk);
+
+        if let VendorTsbk::Regroup(ref r) = v {
+            self.talkgroups.update_patch(r);
+
+            match *r {
+                Regroup::Grant(sg, ch, _) => self.add_talkgroup(TalkGroup::Other(sg), ch),
+                Regroup::Update(ref updates) => {
+                    for &(ch, sg) in updates.iter() {
+                        self.add_talkgroup(TalkGroup::Other(sg), ch);
+                    }
+                },
+                Regroup::Add(..) | Regroup::Delete(..) => {},
+            }
+        }
+
+        self.hub.send(HubEvent::VendorControl(v))
+            .expect("unable to send vendor trunking control");
+    }
+
     /// Process the given link control word.
     fn handle_lc(&mut self, lc: LinkControlFields) {
         use p25::voice::control::LinkControlOpcode;
 
+        if self.raw {
+            self.hub.send(HubEvent::RawPacket(RawPacket::lc(&lc, self.curfreq)))
+                .expect("unable to send raw packet");
+        }
+
         let opcode = match lc.opcode() {
             Some(o) => o,
             None => return,
@@ -284,6 +684,16 @@ impl RecvTask {
 
         trace!("received LC word with opcode {:?}", opcode);
 
+        let kind = match opcode {
+            LinkControlOpcode::NetworkStatusBroadcast => Some(SystemBroadcast::Network),
+            LinkControlOpcode::RfssStatusBroadcast => Some(SystemBroadcast::Rfss),
+            _ => None,
+        };
+
+        if !self.check_system(kind, lc.payload()) {
+            return;
+        }
+
         self.hub.send(HubEvent::LinkControl(lc))
             .expect("unable to send link control");
 
@@ -293,18 +703,58 @@ impl RecvTask {
                 let event = self.policy.handle_call_term();
                 self.handle_policy(event);
             },
+            LinkControlOpcode::GroupVoiceTraffic => {
+                let f = control::GroupVoiceTraffic::new(lc);
+                let freq = self.curfreq;
+
+                self.record_emergency(f.talkgroup(), f.src_unit(), f.opts().emergency(),
+                                      Some(freq));
+            },
             LinkControlOpcode::GroupVoiceUpdate => {
                 self.handle_traffic_updates(
                     &fields::GroupTrafficUpdate::new(lc.payload()));
 
-                if let Some((tg, freq)) = self.talkgroups.select_preempt() {
-                    self.select_talkgroup(tg, freq);
+                if let Some((call, freq)) = self.talkgroups.select_preempt() {
+                    self.select_call(call, freq);
                 }
             },
             _ => {},
         }
     }
 
+    /// Check the system identity carried by the given status broadcast payload, if any,
+    /// returning whether the current packet should be processed.
+    fn check_system(&mut self, kind: Option<SystemBroadcast>, payload: &[u8]) -> bool {
+        let (wacn, sys) = match kind {
+            Some(SystemBroadcast::Network) => {
+                let f = fields::NetworkStatusBroadcast::new(payload);
+                (Some(f.wacn()), f.system())
+            },
+            Some(SystemBroadcast::Rfss) => {
+                let f = fields::RfssStatusBroadcast::new(payload);
+                (None, f.system())
+            },
+            // Other packets are discarded while the channel belongs to another system.
+            None => return !self.identity.foreign(),
+        };
+
+        if self.identity.check_system(wacn, sys) {
+            return true;
+        }
+
+        trace!("rejected packets from system {:03X}", sys);
+
+        false
+    }
+
+    /// Record the given talkgroup activity in the local statistics and the hub's copy.
+    fn record_stats(&mut self, e: StatsEvent) {
+        self.talkgroups.stats_mut().apply(e);
+
+        self.hub.send(HubEvent::UpdateTalkgroupStats(e))
+            .expect("unable to send talkgroup stats");
+    }
+
     /// Collect talkgroups from the given traffic update packet.
     fn handle_traffic_updates(&mut self, u: &fields::GroupTrafficUpdate) {
         for &(ch, tg) in u.updates().iter() {
@@ -312,20 +762,60 @@ impl RecvTask {
         }
     }
 
-    /// Process the given encryption info for the current talkgroup.
-    fn handle_crypto(&mut self, alg: CryptoAlgorithm) {
+    /// Process the given encryption algorithm ID and key ID for the current talkgroup.
+    fn handle_crypto(&mut self, alg_id: u8, key: u16) {
+        let alg = CryptoAlgorithm::from_bits(alg_id);
+
         if let CryptoAlgorithm::Unencrypted = alg {
             return;
         }
 
         self.switch_control();
-        self.talkgroups.record_encrypted(self.curgroup, alg);
+
+        // Only talkgroups are remembered as encrypted.
+        let tg = match self.curcall {
+            Call::Group(tg) => tg,
+            Call::Unit(..) => return,
+        };
+
+        self.talkgroups.record_encrypted(tg, alg);
+        self.record_stats(StatsEvent::Encrypted(tg));
 
         self.hub.send(
-            HubEvent::State(StateEvent::UpdateEncrypted(self.curgroup, alg))
+            HubEvent::State(StateEvent::UpdateEncrypted(tg, alg_id, key))
         ).expect("unable to send encrypted talkgroups");
     }
 
+    /// Track the emergency status of a call on the given talkgroup from the given unit,
+    /// notifying of newly seen emergencies.
+    fn record_emergency(&mut self, tg: TalkGroup, unit: u32, emergency: bool,
+                        freq: Option<u32>)
+    {
+        let tg = match tg {
+            TalkGroup::Other(x) => x,
+            _ => return,
+        };
+
+        if !self.talkgroups.record_emergency(tg, emergency) {
+            return;
+        }
+
+        warn!("emergency call on talkgroup {} from unit {}", tg, unit);
+
+        self.hub.send(HubEvent::Emergency(tg, unit, freq))
+            .expect("unable to send emergency");
+    }
+
+    /// Collect the given private call and associated traffic channel.
+    fn add_private(&mut self, src: u32, dest: u32, ch: Channel) {
+        let freq = match self.channels.rx_freq(ch) {
+            Some(f) => f,
+            None => return,
+        };
+
+        self.talkgroups.add_private(src, dest, freq);
+    }
+
     /// Collect the given talkgroup and associated traffic channel.
     fn add_talkgroup(&mut self, tg: TalkGroup, ch: Channel) {
         let tg = match tg {
@@ -333,11 +823,21 @@ impl RecvTask {
             _ => return,
         };
 
-        let freq = match self.channels.lookup(ch.id()) {
-            Some(p) => p.rx_freq(ch.number()),
+        let freq = match self.channels.rx_freq(ch) {
+            Some(f) => f,
             None => return,
         };
 
         self.talkgroups.add_talkgroup(tg, freq);
     }
 }
+
+/// Get the raw 12-bit value of the given network access code.
+pub fn nac_bits(nac: NetworkAccessCode) -> u16 {
+    match nac {
+        NetworkAccessCode::Default => 0x293,
+        NetworkAccessCode::ReceiveAny => 0xF7E,
+        NetworkAccessCode::RepeatAny => 0xF7F,
+        NetworkAccessCode::Other(x) => x,
+    }
+}
@@ -1,12 +1,20 @@
 //! Replay saved baseband recordings.
 
 use std::io::{Read, Write};
+use std::sync::mpsc::{Sender, Receiver};
+use std::thread;
+use std::time::Duration;
+use std;
 
 use p25::message::receiver::MessageReceiver;
 use p25::stats::Stats;
+use pool::Pool;
 use slice_cast;
 
 use audio::AudioOutput;
+use consts::BUF_SAMPLES;
+use recv::RecvEvent;
+use sdr::ControlTaskEvent;
 
 pub struct ReplayReceiver<W: Write> {
     audio: AudioOutput<W>,
@@ -56,3 +64,171 @@ impl<W: Write> ReplayReceiver<W> {
         }
     }
 }
+
+/// Read all baseband samples (f32le/48kHz/mono) from the given stream.
+pub fn load_baseband<R: Read>(mut stream: R) -> std::io::Result<Vec<f32>> {
+    let mut bytes = vec![];
+    try!(stream.read_to_end(&mut bytes));
+
+    let len = bytes.len() / 4 * 4;
+
+    Ok(unsafe { slice_cast::cast(&bytes[..len]) }.to_vec())
+}
+
+/// Stands in for the SDR tasks by serving baseband recordings for the frequencies
+/// they were captured at, so receiver behavior can be tested without hardware.
+pub struct SimTuner {
+    /// Baseband samples captured at each frequency (Hz).
+    recordings: Vec<(u32, Vec<f32>)>,
+    /// Tuner control messages.
+    events: Receiver<ControlTaskEvent>,
+    /// Channel to send baseband to.
+    recv: Sender<RecvEvent>,
+    /// Index of the recording for the current frequency, or `None` if there is none.
+    cur: Option<usize>,
+    /// Position of the next sample in the current recording.
+    pos: usize,
+}
+
+impl SimTuner {
+    /// Create a new `SimTuner` with the given recordings, receiving tuner messages from
+    /// the given channel.
+    pub fn new(recordings: Vec<(u32, Vec<f32>)>,
+               events: Receiver<ControlTaskEvent>,
+               recv: Sender<RecvEvent>)
+        -> Self
+    {
+        SimTuner {
+            recordings: recordings,
+            events: events,
+            recv: recv,
+            cur: None,
+            pos: 0,
+        }
+    }
+
+    /// Start serving baseband, blocking the thread until the receiver hangs up.
+    pub fn run(&mut self) {
+        let mut pool = Pool::with_capacity(16, || vec![0.0; BUF_SAMPLES]);
+
+        loop {
+            while let Ok(e) = self.events.try_recv() {
+                match e {
+                    ControlTaskEvent::SetFreq(freq) => self.tune(freq),
+                }
+            }
+
+            let mut baseband = match pool.checkout() {
+                Some(b) => b,
+                None => {
+                    thread::sleep(Duration::from_millis(1));
+                    continue;
+                },
+            };
+
+            self.fill(&mut baseband[..]);
+
+            if self.recv.send(RecvEvent::Baseband(baseband)).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Move to the given frequency (Hz), restarting its recording from the beginning.
+    fn tune(&mut self, freq: u32) {
+        self.cur = self.recordings.iter()
+            .position(|&(f, ref samples)| f == freq && !samples.is_empty());
+        self.pos = 0;
+
+        if self.cur.is_none() {
+            debug!("no recording at {} Hz, serving silence", freq);
+        }
+    }
+
+    /// Fill the given buffer with samples from the current recording, looping at the
+    /// end.
+    fn fill(&mut self, buf: &mut [f32]) {
+        let samples = match self.cur {
+            Some(idx) => &self.recordings[idx].1,
+            None => {
+                for s in buf.iter_mut() {
+                    *s = 0.0;
+                }
+
+                return;
+            },
+        };
+
+        for s in buf.iter_mut() {
+            *s = samples[self.pos];
+            self.pos = (self.pos + 1) % samples.len();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    use config::SystemIdentity;
+    use hunt::ControlHunt;
+
+    #[test]
+    fn test_sim_hunt() {
+        // Generated baseband standing in for decoded control channels: nothing at 100,
+        // a control channel of another system at 200, and the expected one at 300.
+        let recordings = vec![
+            (100, vec![0.0; 100]),
+            (200, vec![0.5; 100]),
+            (300, vec![1.0; 100]),
+        ];
+
+        let (tx_ctl, rx_ctl) = channel();
+        let (tx_recv, rx_recv) = channel();
+        let mut sim = SimTuner::new(recordings, rx_ctl, tx_recv);
+        let t = thread::spawn(move || sim.run());
+
+        // The dwell outlasts the buffers still in flight from a previous frequency.
+        let mut hunt = ControlHunt::new(vec![100, 200, 300], SystemIdentity {
+            wacn: Some(0xBEE00),
+            system: Some(0x123),
+            nac: None,
+        }, 0, BUF_SAMPLES * 32);
+
+        let mut freq = 100;
+        tx_ctl.send(ControlTaskEvent::SetFreq(freq)).unwrap();
+
+        for _ in 0..1000 {
+            let samples = match rx_recv.recv().unwrap() {
+                RecvEvent::Baseband(b) => b,
+                _ => panic!("expected baseband"),
+            };
+
+            let system = match samples[0] {
+                s if s == 0.5 => Some(0x124),
+                s if s == 1.0 => Some(0x123),
+                _ => None,
+            };
+
+            if let Some(system) = system {
+                hunt.record_tsbk();
+
+                if hunt.record_network(0xBEE00, system) {
+                    break;
+                }
+            }
+
+            if hunt.record_elapsed(samples.len()) {
+                freq = hunt.next_candidate(freq).unwrap();
+                tx_ctl.send(ControlTaskEvent::SetFreq(freq)).unwrap();
+            }
+        }
+
+        assert!(hunt.locked());
+        assert_eq!(freq, 300);
+
+        drop(rx_recv);
+        t.join().unwrap();
+    }
+}
@@ -0,0 +1,380 @@
+//! Roaming between adjacent sites based on signal quality.
+
+use std;
+
+use consts::BASEBAND_SAMPLE_RATE;
+
+/// Time (sec) to assess the current site before considering a roam.
+const WINDOW_SECS: usize = 10;
+/// Time (sec) to stay on a site after roaming to it before considering another roam.
+const HOLDOFF_SECS: usize = 60;
+/// Time (sec) to stay on a weak site after probing found no better neighbor, doubled for
+/// each consecutive failed probe round.
+const RETRY_SECS: usize = 30;
+/// Maximum time (sec) to stay on a weak site between probe rounds.
+const MAX_RETRY_SECS: usize = 600;
+/// Maximum fraction of packets with decode errors for a site to be usable.
+const MAX_ERROR_RATE: f32 = 0.2;
+
+/// User parameters for roaming.
+#[derive(Copy, Clone)]
+pub struct RoamParams {
+    /// Signal power (dBm) below which the current site is considered weak.
+    pub threshold: f32,
+    /// Amount (dB) a neighbor must be stronger than the current site to roam to it.
+    pub margin: f32,
+    /// Time (baseband samples) to measure each neighbor.
+    pub probe: usize,
+}
+
+/// Signal quality measured over some period.
+#[derive(Copy, Clone, Default)]
+struct Quality {
+    /// Sum of measured power levels (dBm).
+    power: f32,
+    /// Number of power levels measured.
+    levels: usize,
+    /// Number of valid packets.
+    packets: usize,
+    /// Number of decode errors.
+    errors: usize,
+}
+
+impl Quality {
+    /// Average power (dBm), if any was measured.
+    fn avg_power(&self) -> Option<f32> {
+        if self.levels == 0 {
+            None
+        } else {
+            Some(self.power / self.levels as f32)
+        }
+    }
+
+    /// Fraction of packets with decode errors.
+    fn error_rate(&self) -> f32 {
+        let total = self.packets + self.errors;
+
+        if total == 0 {
+            1.0
+        } else {
+            self.errors as f32 / total as f32
+        }
+    }
+
+    /// Score the quality for comparison, or `None` if the channel isn't usable.
+    fn score(&self) -> Option<f32> {
+        if self.packets == 0 || self.error_rate() > MAX_ERROR_RATE {
+            None
+        } else {
+            self.avg_power()
+        }
+    }
+}
+
+/// Control channel of an adjacent site.
+#[derive(Copy, Clone, Eq, PartialEq)]
+struct Neighbor {
+    /// RF subsystem ID.
+    rfss: u8,
+    /// Site ID.
+    site: u8,
+    /// Control channel frequency (Hz).
+    freq: u32,
+}
+
+/// Current roaming activity.
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum RoamState {
+    /// Assessing the current site.
+    Monitor,
+    /// Measuring the neighbor at the contained index.
+    Probe(usize),
+}
+
+/// Action the receiver should take for roaming.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum RoamAction {
+    /// Tune to the given neighbor control channel (Hz) to measure it.
+    Probe(u32),
+    /// No neighbor was better, so return to the current control channel.
+    Return,
+    /// Make the given neighbor control channel (Hz) the new control channel.
+    Move(u32),
+}
+
+/// Tracks signal quality of the current site and its neighbors and decides when to
+/// move to a better site.
+pub struct SiteRoamer {
+    /// Roaming parameters, or `None` if roaming is disabled.
+    params: Option<RoamParams>,
+    /// System ID of the current site.
+    system: Option<u16>,
+    /// Adjacent sites in the same system.
+    neighbors: Vec<Neighbor>,
+    /// Current activity.
+    state: RoamState,
+    /// Time (baseband samples) spent in the current activity.
+    elapsed: usize,
+    /// Time (baseband samples) remaining before roaming is considered again.
+    holdoff: usize,
+    /// Number of consecutive probe rounds that found no better neighbor.
+    failures: u32,
+    /// Quality being measured for the current activity.
+    quality: Quality,
+    /// Score of the current site when probing started.
+    cur_score: f32,
+    /// Best neighbor frequency (Hz) and score found while probing.
+    best: Option<(u32, f32)>,
+}
+
+impl SiteRoamer {
+    /// Create a new `SiteRoamer` with the given parameters, or disabled if `None`.
+    pub fn new(params: Option<RoamParams>) -> Self {
+        SiteRoamer {
+            params: params,
+            system: None,
+            neighbors: vec![],
+            state: RoamState::Monitor,
+            elapsed: 0,
+            holdoff: 0,
+            failures: 0,
+            quality: Quality::default(),
+            cur_score: std::f32::NEG_INFINITY,
+            best: None,
+        }
+    }
+
+    /// Forget the current site, such as when moving to a different control channel.
+    pub fn reset(&mut self) {
+        self.system = None;
+        self.neighbors.clear();
+        self.state = RoamState::Monitor;
+        self.elapsed = 0;
+        self.quality = Quality::default();
+        self.best = None;
+    }
+
+    /// Check if a neighbor is currently being measured.
+    pub fn probing(&self) -> bool {
+        self.state != RoamState::Monitor
+    }
+
+    /// Record the system ID of the current site.
+    pub fn record_system(&mut self, system: u16) {
+        if self.system != Some(system) {
+            self.neighbors.clear();
+        }
+
+        self.system = Some(system);
+    }
+
+    /// Record the given adjacent site and its control channel frequency (Hz).
+    pub fn add_neighbor(&mut self, system: u16, rfss: u8, site: u8, freq: u32) {
+        if self.params.is_none() || self.probing() || self.system != Some(system) {
+            return;
+        }
+
+        self.neighbors.retain(|n| (n.rfss, n.site) != (rfss, site));
+        self.neighbors.push(Neighbor {
+            rfss: rfss,
+            site: site,
+            freq: freq,
+        });
+    }
+
+    /// Record a measured signal power (dBm).
+    pub fn record_power(&mut self, power: f32) {
+        self.quality.power += power;
+        self.quality.levels += 1;
+    }
+
+    /// Record a valid packet.
+    pub fn record_packet(&mut self) {
+        self.quality.packets += 1;
+    }
+
+    /// Record a decode error.
+    pub fn record_error(&mut self) {
+        self.quality.errors += 1;
+    }
+
+    /// Record the given elapsed baseband samples on the control channel and return any
+    /// roaming action to take.
+    pub fn record_elapsed(&mut self, samples: usize) -> Option<RoamAction> {
+        let params = match self.params {
+            Some(p) => p,
+            None => return None,
+        };
+
+        self.elapsed += samples;
+
+        match self.state {
+            RoamState::Monitor => {
+                self.holdoff = self.holdoff.saturating_sub(samples);
+
+                if self.elapsed < WINDOW_SECS * BASEBAND_SAMPLE_RATE as usize {
+                    return None;
+                }
+
+                let q = self.finish();
+
+                if self.holdoff > 0 || self.neighbors.is_empty() {
+                    return None;
+                }
+
+                let weak = q.score().map_or(true, |s| s < params.threshold);
+
+                if !weak {
+                    self.failures = 0;
+                    return None;
+                }
+
+                self.cur_score = q.score().unwrap_or(std::f32::NEG_INFINITY);
+                self.best = None;
+                self.state = RoamState::Probe(0);
+
+                Some(RoamAction::Probe(self.neighbors[0].freq))
+            },
+            RoamState::Probe(idx) => {
+                if self.elapsed < params.probe {
+                    return None;
+                }
+
+                let q = self.finish();
+                let n = self.neighbors[idx];
+
+                if let Some(score) = q.score() {
+                    debug!("neighbor rfss {} site {} measured {:.1} dBm", n.rfss, n.site,
+                           score);
+
+                    let better = score >= self.cur_score + params.margin &&
+                        self.best.map_or(true, |(_, b)| score > b);
+
+                    if better {
+                        self.best = Some((n.freq, score));
+                    }
+                }
+
+                if idx + 1 < self.neighbors.len() {
+                    self.state = RoamState::Probe(idx + 1);
+                    return Some(RoamAction::Probe(self.neighbors[idx + 1].freq));
+                }
+
+                self.state = RoamState::Monitor;
+
+                match self.best.take() {
+                    Some((freq, _)) => {
+                        self.failures = 0;
+                        self.holdoff = HOLDOFF_SECS * BASEBAND_SAMPLE_RATE as usize;
+                        Some(RoamAction::Move(freq))
+                    },
+                    None => {
+                        // Back off so a persistently weak site isn't left every window.
+                        let retry = std::cmp::min(
+                            RETRY_SECS << std::cmp::min(self.failures, 8), MAX_RETRY_SECS);
+
+                        self.failures = self.failures.saturating_add(1);
+                        self.holdoff = retry * BASEBAND_SAMPLE_RATE as usize;
+
+                        Some(RoamAction::Return)
+                    },
+                }
+            },
+        }
+    }
+
+    /// Finish the current measurement period and return its quality.
+    fn finish(&mut self) -> Quality {
+        let q = self.quality;
+
+        self.quality = Quality::default();
+        self.elapsed = 0;
+
+        q
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const WINDOW: usize = WINDOW_SECS * BASEBAND_SAMPLE_RATE as usize;
+
+    fn measure(r: &mut SiteRoamer, power: f32) {
+        r.record_power(power);
+        r.record_packet();
+    }
+
+    #[test]
+    fn test_roam() {
+        let mut r = SiteRoamer::new(Some(RoamParams {
+            threshold: -20.0,
+            margin: 6.0,
+            probe: 100,
+        }));
+
+        r.record_system(0x123);
+        r.add_neighbor(0x123, 1, 2, 100);
+        r.add_neighbor(0x456, 1, 3, 200);
+        r.add_neighbor(0x123, 1, 4, 300);
+
+        // Strong current site.
+        measure(&mut r, -10.0);
+        assert_eq!(r.record_elapsed(WINDOW), None);
+        assert!(!r.probing());
+
+        // Weak current site with one good neighbor.
+        measure(&mut r, -30.0);
+        assert_eq!(r.record_elapsed(WINDOW), Some(RoamAction::Probe(100)));
+        assert!(r.probing());
+        measure(&mut r, -26.0);
+        assert_eq!(r.record_elapsed(99), None);
+        assert_eq!(r.record_elapsed(1), Some(RoamAction::Probe(300)));
+        measure(&mut r, -15.0);
+        assert_eq!(r.record_elapsed(100), Some(RoamAction::Move(300)));
+        assert!(!r.probing());
+
+        // Holdoff after moving.
+        r.reset();
+        r.record_system(0x123);
+        r.add_neighbor(0x123, 1, 2, 100);
+        measure(&mut r, -30.0);
+        assert_eq!(r.record_elapsed(WINDOW), None);
+
+        let mut r = SiteRoamer::new(Some(RoamParams {
+            threshold: -20.0,
+            margin: 6.0,
+            probe: 100,
+        }));
+
+        // No better neighbor.
+        r.record_system(0x123);
+        r.add_neighbor(0x123, 1, 2, 100);
+        measure(&mut r, -30.0);
+        assert_eq!(r.record_elapsed(WINDOW), Some(RoamAction::Probe(100)));
+        assert_eq!(r.record_elapsed(100), Some(RoamAction::Return));
+
+        // Probing is held off after a failed round, for longer after each.
+        for _ in 0..2 {
+            measure(&mut r, -30.0);
+            assert_eq!(r.record_elapsed(WINDOW), None);
+        }
+
+        measure(&mut r, -30.0);
+        assert_eq!(r.record_elapsed(WINDOW), Some(RoamAction::Probe(100)));
+        assert_eq!(r.record_elapsed(100), Some(RoamAction::Return));
+
+        for _ in 0..5 {
+            measure(&mut r, -30.0);
+            assert_eq!(r.record_elapsed(WINDOW), None);
+        }
+
+        measure(&mut r, -30.0);
+        assert_eq!(r.record_elapsed(WINDOW), Some(RoamAction::Probe(100)));
+
+        let mut r = SiteRoamer::new(None);
+        r.record_system(0x123);
+        r.add_neighbor(0x123, 1, 2, 100);
+        assert_eq!(r.record_elapsed(WINDOW), None);
+    }
+}
@@ -0,0 +1,354 @@
+//! Band scanning for P25 control channels.
+
+use std::io::Write;
+use std::sync::mpsc::{Sender, Receiver};
+use std;
+
+use mio_more;
+use p25::message::receiver::MessageReceiver;
+use p25::trunking::fields;
+use p25::trunking::tsbk::{TsbkOpcode, TsbkFields};
+use pool::Checkout;
+use serde_json;
+
+use hub::HubEvent;
+use recv::{nac_bits, RecvEvent};
+use sdr::ControlTaskEvent;
+
+/// Number of baseband chunks to discard after tuning, which may contain samples from the
+/// previous frequency.
+const SETTLE_CHUNKS: usize = 4;
+
+/// Multiple of the measurement time to wait for a signal power level before giving up
+/// on a frequency.
+const MEASURE_LIMIT: usize = 4;
+
+/// Parameters for a band scan.
+pub struct ScanParams {
+    /// First frequency to scan (Hz).
+    pub start: u32,
+    /// Last frequency to scan (Hz).
+    pub stop: u32,
+    /// Step between frequencies (Hz).
+    pub step: u32,
+    /// Time (baseband samples) to measure signal power at each frequency.
+    pub measure: usize,
+    /// Time (baseband samples) to attempt decoding at each promising frequency.
+    pub sync: usize,
+    /// Minimum signal power (dBm) to attempt decoding.
+    pub threshold: f32,
+}
+
+/// Discovered control channel.
+#[derive(Serialize)]
+pub struct ControlChannel {
+    /// Frequency (Hz).
+    pub freq: u32,
+    /// Average signal power (dBm).
+    pub power: f32,
+    /// Network access code.
+    pub nac: Option<u16>,
+    /// Wide area communications network ID.
+    pub wacn: Option<u32>,
+    /// System ID.
+    pub system: Option<u16>,
+    /// RF subsystem ID.
+    pub rfss: Option<u8>,
+    /// Site ID.
+    pub site: Option<u8>,
+    /// Number of valid trunking packets received.
+    #[serde(skip_serializing)]
+    tsbks: usize,
+}
+
+impl ControlChannel {
+    /// Create a new `ControlChannel` with nothing yet decoded.
+    fn new(freq: u32, power: f32) -> Self {
+        ControlChannel {
+            freq: freq,
+            power: power,
+            nac: None,
+            wacn: None,
+            system: None,
+            rfss: None,
+            site: None,
+            tsbks: 0,
+        }
+    }
+
+    /// Check if all identity fields have been decoded.
+    fn complete(&self) -> bool {
+        self.nac.is_some() && self.wacn.is_some() && self.system.is_some() &&
+            self.rfss.is_some() && self.site.is_some()
+    }
+
+    /// Record any identity information in the given trunking packet.
+    fn handle_tsbk(&mut self, tsbk: TsbkFields) {
+        if tsbk.mfg() != 0 || !tsbk.crc_valid() {
+            return;
+        }
+
+        self.tsbks += 1;
+
+        match tsbk.opcode() {
+            Some(TsbkOpcode::NetworkStatusBroadcast) => {
+                let f = fields::NetworkStatusBroadcast::new(tsbk.payload());
+                self.wacn = Some(f.wacn());
+                self.system = Some(f.system());
+            },
+            Some(TsbkOpcode::RfssStatusBroadcast) => {
+                let f = fields::RfssStatusBroadcast::new(tsbk.payload());
+                self.system = Some(f.system());
+                self.rfss = Some(f.rfss());
+                self.site = Some(f.site());
+            },
+            _ => {},
+        }
+    }
+}
+
+/// Sweeps a frequency range looking for P25 control channels.
+pub struct ScanTask {
+    /// Baseband chunks from the demodulator.
+    events: Receiver<RecvEvent>,
+    /// Signal power updates from the demodulator.
+    hub: mio_more::channel::Receiver<HubEvent>,
+    /// SDR control task.
+    sdr: Sender<ControlTaskEvent>,
+    /// Scan parameters.
+    params: ScanParams,
+}
+
+impl ScanTask {
+    /// Create a new `ScanTask` communicating on the given channels.
+    pub fn new(events: Receiver<RecvEvent>,
+               hub: mio_more::channel::Receiver<HubEvent>,
+               sdr: Sender<ControlTaskEvent>,
+               params: ScanParams)
+        -> Self
+    {
+        ScanTask {
+            events: events,
+            hub: hub,
+            sdr: sdr,
+            params: params,
+        }
+    }
+
+    /// Scan the whole range, blocking the current thread, and return the discovered
+    /// control channels.
+    pub fn run(&mut self) -> Vec<ControlChannel> {
+        let mut found = vec![];
+        let mut freq = self.params.start;
+
+        while freq <= self.params.stop {
+            if let Some(c) = self.probe(freq) {
+                info!("found control channel at {} Hz", freq);
+                found.push(c);
+            }
+
+            freq = match freq.checked_add(self.params.step) {
+                Some(f) => f,
+                None => break,
+            };
+        }
+
+        found
+    }
+
+    /// Check for a control channel at the given frequency (Hz).
+    fn probe(&mut self, freq: u32) -> Option<ControlChannel> {
+        self.tune(freq);
+
+        let power = match self.measure() {
+            Some(p) => p,
+            None => {
+                warn!("no signal power reported at {} Hz", freq);
+                return None;
+            },
+        };
+
+        debug!("measured {:.1} dBm at {} Hz", power, freq);
+
+        if power < self.params.threshold {
+            return None;
+        }
+
+        self.decode(ControlChannel::new(freq, power))
+    }
+
+    /// Move to the given frequency (Hz) and wait for samples from it.
+    fn tune(&mut self, freq: u32) {
+        self.sdr.send(ControlTaskEvent::SetFreq(freq))
+            .expect("unable to set freq in sdr");
+
+        for _ in 0..SETTLE_CHUNKS {
+            self.next_baseband();
+        }
+
+        // Discard power levels measured before tuning.
+        while self.hub.try_recv().is_ok() {}
+    }
+
+    /// Measure the average signal power (dBm) at the current frequency, or return
+    /// `None` if no power was reported.
+    fn measure(&mut self) -> Option<f32> {
+        let mut levels = vec![];
+        let mut elapsed = 0;
+        let limit = self.params.measure.saturating_mul(MEASURE_LIMIT);
+
+        // Power is only reported for some chunks, so wait for at least one level.
+        while elapsed < self.params.measure || levels.is_empty() && elapsed < limit {
+            elapsed += self.next_baseband().len();
+
+            while let Ok(e) = self.hub.try_recv() {
+                if let HubEvent::UpdateSignalPower(p) = e {
+                    levels.push(p);
+                }
+            }
+        }
+
+        if levels.is_empty() {
+            None
+        } else {
+            Some(levels.iter().fold(0.0, |s, &p| s + p) / levels.len() as f32)
+        }
+    }
+
+    /// Attempt to decode trunking packets at the current frequency, returning the
+    /// channel if any were received.
+    fn decode(&mut self, mut chan: ControlChannel) -> Option<ControlChannel> {
+        use p25::message::receiver::MessageEvent::*;
+
+        let mut msg = MessageReceiver::new();
+        let mut elapsed = 0;
+
+        while elapsed < self.params.sync && !chan.complete() {
+            let baseband = self.next_baseband();
+            elapsed += baseband.len();
+
+            for &s in baseband.iter() {
+                match msg.feed(s) {
+                    Some(PacketNID(nid)) => chan.nac = Some(nac_bits(nid.access_code)),
+                    Some(TrunkingControl(tsbk)) => chan.handle_tsbk(tsbk),
+                    _ => {},
+                }
+            }
+        }
+
+        if chan.tsbks == 0 {
+            None
+        } else {
+            Some(chan)
+        }
+    }
+
+    /// Wait for the next chunk of baseband.
+    fn next_baseband(&mut self) -> Checkout<Vec<f32>> {
+        loop {
+            match self.events.recv().expect("unable to receive baseband") {
+                RecvEvent::Baseband(b) => return b,
+                _ => {},
+            }
+        }
+    }
+}
+
+/// Write the given control channels as a JSON array.
+pub fn write_json<W: Write>(mut stream: W, chans: &[ControlChannel])
+    -> std::io::Result<()>
+{
+    try!(serde_json::to_writer_pretty(&mut stream, chans)
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other,
+                                         "unable to serialize scan results")));
+
+    writeln!(stream, "")
+}
+
+/// Write the given control channels as CSV with a header row.
+pub fn write_csv<W: Write>(mut stream: W, chans: &[ControlChannel])
+    -> std::io::Result<()>
+{
+    try!(writeln!(stream, "freq,power,nac,wacn,system,rfss,site"));
+
+    for c in chans {
+        try!(writeln!(stream, "{},{:.1},{},{},{},{},{}", c.freq, c.power,
+                      csv_field(c.nac), csv_field(c.wacn), csv_field(c.system),
+                      csv_field(c.rfss), csv_field(c.site)));
+    }
+
+    Ok(())
+}
+
+/// Format the given optional value as a CSV field, leaving it empty if unknown.
+fn csv_field<T: ToString>(x: Option<T>) -> String {
+    x.map(|x| x.to_string()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::Value;
+    use signaling::build_tsbk;
+
+    #[test]
+    fn test_handle_tsbk() {
+        let mut c = ControlChannel::new(851000000, -40.0);
+
+        // Network status for WACN BEE00 and system 123.
+        c.handle_tsbk(TsbkFields::new(build_tsbk(0, 0x3B, [
+            0x01, 0xBE, 0xE0, 0x01, 0x23, 0x10, 0x01, 0x70,
+        ])));
+        assert_eq!(c.tsbks, 1);
+        assert_eq!(c.wacn, Some(0xBEE00));
+        assert_eq!(c.system, Some(0x123));
+        assert!(!c.complete());
+
+        // Corrupted and manufacturer-specific packets are ignored.
+        let mut bad = build_tsbk(0, 0x3A, [0; 8]);
+        bad[11] ^= 1;
+        c.handle_tsbk(TsbkFields::new(bad));
+
+        c.handle_tsbk(TsbkFields::new(build_tsbk(0x90, 0x00, [0; 8])));
+        assert_eq!(c.tsbks, 1);
+
+        // RFSS status for system 123, RFSS 1, site 2.
+        c.handle_tsbk(TsbkFields::new(build_tsbk(0, 0x3A, [
+            0x01, 0x01, 0x23, 0x01, 0x02, 0x10, 0x01, 0x70,
+        ])));
+        assert_eq!(c.tsbks, 2);
+        assert_eq!(c.rfss, Some(1));
+        assert_eq!(c.site, Some(2));
+        assert!(!c.complete());
+
+        c.nac = Some(0x293);
+        assert!(c.complete());
+    }
+
+    #[test]
+    fn test_write() {
+        let mut a = ControlChannel::new(851000000, -40.5);
+        a.nac = Some(0x293);
+        a.system = Some(0x123);
+        let b = ControlChannel::new(852500000, -55.0);
+
+        let mut buf = vec![];
+        write_csv(&mut buf, &[a, b]).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(),
+                   "freq,power,nac,wacn,system,rfss,site\n\
+                    851000000,-40.5,659,,291,,\n\
+                    852500000,-55.0,,,,,\n");
+
+        assert_eq!(csv_field(Some(42)), "42");
+        assert_eq!(csv_field::<u8>(None), "");
+
+        let mut buf = vec![];
+        write_json(&mut buf, &[ControlChannel::new(851000000, -40.0)]).unwrap();
+        assert_eq!(buf.last(), Some(&b'\n'));
+
+        let v: Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(v[0]["freq"].as_u64(), Some(851000000));
+        assert!(v[0]["nac"].is_null());
+        assert!(v[0]["tsbks"].is_null());
+    }
+}
@@ -0,0 +1,208 @@
+//! Day-of-week and time-of-day schedules.
+
+use chrono::{Datelike, Local, Timelike};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serializer};
+
+/// Day of the week.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Day {
+    #[serde(rename = "mon")]
+    Monday,
+    #[serde(rename = "tue")]
+    Tuesday,
+    #[serde(rename = "wed")]
+    Wednesday,
+    #[serde(rename = "thu")]
+    Thursday,
+    #[serde(rename = "fri")]
+    Friday,
+    #[serde(rename = "sat")]
+    Saturday,
+    #[serde(rename = "sun")]
+    Sunday,
+}
+
+impl Day {
+    /// Get the day for the given number of days from Monday.
+    fn from_monday(days: u32) -> Self {
+        use self::Day::*;
+
+        [Monday, Tuesday, Wednesday, Thursday, Friday, Saturday, Sunday][days as usize % 7]
+    }
+
+    /// Get the day before this one.
+    fn prev(self) -> Self {
+        Day::from_monday(self as u32 + 6)
+    }
+}
+
+/// Local day and time used to evaluate schedules.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct LocalTime {
+    /// Day of the week.
+    pub day: Day,
+    /// Minutes since midnight.
+    pub minute: u16,
+}
+
+impl LocalTime {
+    /// Get the current local day and time.
+    pub fn now() -> Self {
+        let now = Local::now();
+
+        LocalTime {
+            day: Day::from_monday(now.weekday().num_days_from_monday()),
+            minute: (now.hour() * 60 + now.minute()) as u16,
+        }
+    }
+}
+
+/// Window of time within a day, which wraps past midnight if it ends before it starts.
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct Window {
+    /// Start of the window (minutes since midnight) as "HH:MM".
+    #[serde(serialize_with = "ser_time", deserialize_with = "de_time")]
+    start: u16,
+    /// End of the window (minutes since midnight, exclusive) as "HH:MM".
+    #[serde(serialize_with = "ser_time", deserialize_with = "de_time")]
+    end: u16,
+}
+
+impl Window {
+    /// Get the day the window containing the given local time started on, or `None` if
+    /// the time falls outside the window.
+    fn start_day(&self, t: LocalTime) -> Option<Day> {
+        if self.start <= self.end {
+            if self.start <= t.minute && t.minute < self.end {
+                Some(t.day)
+            } else {
+                None
+            }
+        } else if t.minute >= self.start {
+            Some(t.day)
+        } else if t.minute < self.end {
+            // The part after midnight belongs to the window started the day before.
+            Some(t.day.prev())
+        } else {
+            None
+        }
+    }
+}
+
+/// Days and time windows when something is in effect.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Schedule {
+    /// Days the schedule applies on, or every day if empty.
+    #[serde(default)]
+    days: Vec<Day>,
+    /// Time windows the schedule applies in, or the whole day if empty.
+    #[serde(default)]
+    windows: Vec<Window>,
+}
+
+impl Schedule {
+    /// Check if the schedule is in effect at the given local time.
+    pub fn active(&self, t: LocalTime) -> bool {
+        if self.windows.is_empty() {
+            return self.on(t.day);
+        }
+
+        self.windows.iter().filter_map(|w| w.start_day(t)).any(|d| self.on(d))
+    }
+
+    /// Check if the schedule applies on the given day.
+    fn on(&self, day: Day) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+}
+
+/// Parse a time in "HH:MM" format to minutes since midnight.
+fn parse_time(s: &str) -> Option<u16> {
+    let mut parts = s.splitn(2, ':');
+
+    let hour: u16 = match parts.next().and_then(|h| h.parse().ok()) {
+        Some(h) if h <= 24 => h,
+        _ => return None,
+    };
+
+    let minute: u16 = match parts.next().and_then(|m| m.parse().ok()) {
+        Some(m) if m < 60 => m,
+        _ => return None,
+    };
+
+    match hour * 60 + minute {
+        t if t <= 24 * 60 => Some(t),
+        _ => None,
+    }
+}
+
+/// Deserialize a time in "HH:MM" format to minutes since midnight.
+fn de_time<D: Deserializer>(d: D) -> Result<u16, D::Error> {
+    let s = try!(String::deserialize(d));
+    parse_time(&s).ok_or_else(|| D::Error::custom("invalid time, expected HH:MM"))
+}
+
+/// Serialize minutes since midnight as a time in "HH:MM" format.
+fn ser_time<S: Serializer>(t: &u16, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&format!("{:02}:{:02}", t / 60, t % 60))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json;
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("00:00"), Some(0));
+        assert_eq!(parse_time("7:30"), Some(450));
+        assert_eq!(parse_time("24:00"), Some(1440));
+        assert_eq!(parse_time("24:01"), None);
+        assert_eq!(parse_time("12:60"), None);
+        assert_eq!(parse_time("12"), None);
+        assert_eq!(parse_time("ab:cd"), None);
+    }
+
+    #[test]
+    fn test_schedule() {
+        let s: Schedule = serde_json::from_str(r#"{
+            "days": ["mon", "fri"],
+            "windows": [{"start": "07:00", "end": "19:00"}]
+        }"#).unwrap();
+
+        let t = |day, minute| LocalTime { day: day, minute: minute };
+
+        assert!(s.active(t(Day::Monday, 420)));
+        assert!(s.active(t(Day::Friday, 1139)));
+        assert!(!s.active(t(Day::Friday, 1140)));
+        assert!(!s.active(t(Day::Monday, 419)));
+        assert!(!s.active(t(Day::Tuesday, 600)));
+
+        let s: Schedule = serde_json::from_str(r#"{
+            "windows": [{"start": "22:00", "end": "06:00"}]
+        }"#).unwrap();
+
+        assert!(s.active(t(Day::Sunday, 1380)));
+        assert!(s.active(t(Day::Sunday, 0)));
+        assert!(!s.active(t(Day::Sunday, 360)));
+
+        // The part of a window after midnight belongs to the day it started.
+        let s: Schedule = serde_json::from_str(r#"{
+            "days": ["fri"],
+            "windows": [{"start": "22:00", "end": "06:00"}]
+        }"#).unwrap();
+
+        assert!(s.active(t(Day::Friday, 1380)));
+        assert!(s.active(t(Day::Saturday, 120)));
+        assert!(!s.active(t(Day::Friday, 120)));
+        assert!(!s.active(t(Day::Saturday, 1380)));
+        assert!(!s.active(t(Day::Saturday, 360)));
+
+        assert!(Schedule::default().active(t(Day::Wednesday, 0)));
+
+        assert!(serde_json::from_str::<Schedule>(r#"{
+            "windows": [{"start": "25:00", "end": "06:00"}]
+        }"#).is_err());
+    }
+}
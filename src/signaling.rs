@@ -0,0 +1,542 @@
+//! Decoding of standard trunking packets into stream events.
+//!
+//! Packets with their own typed handling in the hub (site and network status,
+//! registration responses, and so on) produce no events here. Packets with opcodes the
+//! decoder doesn't know are decoded from their raw opcode, so opcodes without a typed
+//! representation still produce events.
+
+use p25::trunking::fields::{self, Channel, TalkGroup};
+use p25::trunking::tsbk::{self, TsbkFields, TsbkOpcode};
+use serde_json::Value;
+
+use channels::ChannelTable;
+use vendor;
+
+/// Event decoded from a trunking packet.
+pub struct TsbkEvent {
+    /// Event type name.
+    pub name: &'static str,
+    /// Talkgroup the event pertains to, if any.
+    pub talkgroup: Option<u16>,
+    /// Event payload.
+    pub payload: Value,
+}
+
+impl TsbkEvent {
+    fn new(name: &'static str, payload: Value) -> Self {
+        TsbkEvent {
+            name: name,
+            talkgroup: None,
+            payload: payload,
+        }
+    }
+
+    fn group(name: &'static str, tg: Option<u16>, payload: Value) -> Self {
+        TsbkEvent {
+            name: name,
+            talkgroup: tg,
+            payload: payload,
+        }
+    }
+}
+
+/// Decode the given standard trunking packet, using the given channel table to resolve
+/// channel frequencies.
+pub fn decode(tsbk: TsbkFields, channels: &ChannelTable) -> Vec<TsbkEvent> {
+    let opcode = match tsbk.opcode() {
+        Some(o) => o,
+        None => return vec![decode_raw(tsbk.raw_opcode(), tsbk.payload(), channels)],
+    };
+
+    let p = tsbk.payload();
+
+    let e = match opcode {
+        TsbkOpcode::GroupVoiceGrant => {
+            let f = tsbk::GroupVoiceGrant::new(tsbk);
+            let tg = talkgroup(f.talkgroup());
+
+            TsbkEvent::group("groupGrant", tg, json!({
+                "opts": opts(p[0]),
+                "freq": channels.rx_freq(f.channel()),
+                "talkgroup": tg,
+                "unit": f.src_unit(),
+            }))
+        },
+        // Each update carries two channel/talkgroup pairs.
+        TsbkOpcode::GroupVoiceUpdate => {
+            return fields::GroupTrafficUpdate::new(p).updates().iter()
+                .map(|&(ch, tg)| update_event(talkgroup(tg), channels.rx_freq(ch)))
+                .collect();
+        },
+        // Channel T carries the call, while channel R is the inbound channel.
+        TsbkOpcode::GroupVoiceUpdateExplicit => {
+            let tg = Some(be16(&p[6..8]));
+
+            TsbkEvent::group("groupGrantUpdate", tg, json!({
+                "opts": opts(p[0]),
+                "talkgroup": tg,
+                "freq": freq(channels, &p[2..4]),
+            }))
+        },
+        TsbkOpcode::UnitVoiceGrant => unit_grant_event("unitGrant", tsbk, channels),
+        TsbkOpcode::UnitVoiceUpdate =>
+            unit_grant_event("unitGrantUpdate", tsbk, channels),
+        TsbkOpcode::UnitCallRequest => TsbkEvent::new("unitAnswerRequest", json!({
+            "opts": opts(p[0]),
+            "dest": be24(&p[2..5]),
+            "src": be24(&p[5..8]),
+        })),
+        TsbkOpcode::PhoneGrant => phone_grant_event("phoneGrant", p, channels),
+        TsbkOpcode::PhoneAlert => TsbkEvent::new("phoneAnswerRequest", json!({
+            "digits": vendor::hex(&p[0..5]),
+            "unit": be24(&p[5..8]),
+        })),
+        // Data channel opcodes superseded by SNDCP have no decoded fields.
+        TsbkOpcode::UnitDataGrant => opaque_event("unitDataGrant", p),
+        TsbkOpcode::GroupDataGrant => opaque_event("groupDataGrant", p),
+        TsbkOpcode::GroupDataAnnounce => opaque_event("groupDataAnnounce", p),
+        TsbkOpcode::GroupDataAnnounceExplicit =>
+            opaque_event("groupDataAnnounceExplicit", p),
+        TsbkOpcode::UnitStatusUpdate => TsbkEvent::new("statusUpdate", json!({
+            "unitStatus": p[0],
+            "userStatus": p[1],
+            "dest": be24(&p[2..5]),
+            "src": be24(&p[5..8]),
+        })),
+        TsbkOpcode::UnitStatusQuery => unit_event("statusQuery", p),
+        TsbkOpcode::UnitShortMessage => TsbkEvent::new("shortMessage", json!({
+            "message": be16(&p[0..2]),
+            "dest": be24(&p[2..5]),
+            "src": be24(&p[5..8]),
+        })),
+        TsbkOpcode::UnitMonitor => unit_event("radioMonitor", p),
+        TsbkOpcode::UnitCallAlert => unit_event("callAlert", p),
+        TsbkOpcode::AckResponse => TsbkEvent::new("ackResponse", json!({
+            "service": p[0] & 0x3F,
+            "unit": be24(&p[5..8]),
+        })),
+        TsbkOpcode::QueuedResponse => response_event("queuedResponse", p),
+        TsbkOpcode::ExtendedFunctionResponse => TsbkEvent::new("extendedFunction", json!({
+            "class": p[0],
+            "operand": p[1],
+            "args": be24(&p[2..5]),
+            "unit": be24(&p[5..8]),
+        })),
+        TsbkOpcode::DenyResponse => response_event("denyResponse", p),
+        TsbkOpcode::GroupAffiliationResponse => {
+            let tg = be16(&p[3..5]);
+
+            TsbkEvent::group("groupAffiliation", Some(tg), json!({
+                "response": p[0] & 0b11,
+                "global": p[0] >> 7 == 1,
+                "announceGroup": be16(&p[1..3]),
+                "talkgroup": tg,
+                "unit": be24(&p[5..8]),
+            }))
+        },
+        TsbkOpcode::GroupAffiliationQuery => unit_event("affiliationQuery", p),
+        TsbkOpcode::UnitRegCommand => unit_event("unitRegCommand", p),
+        TsbkOpcode::AuthCommand => opaque_event("authCommand", p),
+        TsbkOpcode::RoamingAddrCommand => opaque_event("roamingAddrCommand", p),
+        TsbkOpcode::RoamingAddrUpdate => opaque_event("roamingAddrUpdate", p),
+        TsbkOpcode::SystemServiceBroadcast => TsbkEvent::new("systemServices", json!({
+            "available": be24(&p[1..4]),
+            "supported": be24(&p[4..7]),
+            "priority": p[7],
+        })),
+        TsbkOpcode::ChannelParamsUpdate => {
+            let f = fields::ChannelParamsUpdate::new(p);
+            let params = f.params();
+
+            TsbkEvent::new("idenUpdate", json!({
+                "kind": "standard",
+                "id": f.id(),
+                "channelType": null,
+                "bandwidth": params.bandwidth,
+                "offset": params.offset,
+                "spacing": params.spacing,
+                "base": params.base,
+            }))
+        },
+        TsbkOpcode::LocRegResponse |
+        TsbkOpcode::UnitRegResponse |
+        TsbkOpcode::UnitDeregAck |
+        TsbkOpcode::AltControlChannel |
+        TsbkOpcode::RfssStatusBroadcast |
+        TsbkOpcode::NetworkStatusBroadcast |
+        TsbkOpcode::AdjacentSite => return vec![],
+    };
+
+    vec![e]
+}
+
+/// Decode the given raw opcode, unknown to the packet decoder, and payload.
+fn decode_raw(opcode: u8, p: &[u8], channels: &ChannelTable) -> TsbkEvent {
+    match opcode {
+        0x09 => phone_grant_event("phoneGrantUpdate", p, channels),
+        0x14 => TsbkEvent::new("dataGrant", json!({
+            "opts": opts(p[0]),
+            "freq": freq(channels, &p[3..5]),
+            "unit": be24(&p[5..8]),
+        })),
+        0x15 => TsbkEvent::new("dataPageRequest", json!({
+            "opts": opts(p[0]),
+            "unit": be24(&p[5..8]),
+        })),
+        0x16 => TsbkEvent::new("dataAnnounce", json!({
+            "opts": opts(p[0]),
+            "freq": freq(channels, &p[4..6]),
+        })),
+        // Each broadcast carries two channel/service class pairs.
+        0x29 => TsbkEvent::new("secondaryControl", json!({
+            "rfss": p[0],
+            "site": p[1],
+            "channels": [
+                {"freq": freq(channels, &p[2..4]), "services": p[4]},
+                {"freq": freq(channels, &p[5..7]), "services": p[7]}
+            ],
+        })),
+        0x33 | 0x34 => iden_event(opcode, p),
+        0x35 => time_date_event(p),
+        _ => TsbkEvent::new("tsbk", json!({
+            "opcode": opcode,
+            "payload": vendor::hex(p),
+        })),
+    }
+}
+
+/// Get the talkgroup and unit of the given packet if it's an accepted group affiliation
+/// response.
+pub fn affiliation(tsbk: TsbkFields) -> Option<(u16, u32)> {
+    let p = tsbk.payload();
+
+    match tsbk.opcode() {
+        Some(TsbkOpcode::GroupAffiliationResponse) if p[0] & 0b11 == 0 =>
+            Some((be16(&p[3..5]), be24(&p[5..8]))),
+        _ => None,
+    }
+}
+
+/// Get the ID of the given talkgroup, if it isn't one of the special talkgroups.
+fn talkgroup(tg: TalkGroup) -> Option<u16> {
+    match tg {
+        TalkGroup::Other(tg) => Some(tg),
+        _ => None,
+    }
+}
+
+/// Resolve the frequency (Hz) of the channel in the given 16-bit field.
+fn freq(channels: &ChannelTable, b: &[u8]) -> Option<u32> {
+    channels.rx_freq(Channel::new(b))
+}
+
+/// Create a unit-to-unit voice grant or grant update event.
+fn unit_grant_event(name: &'static str, tsbk: TsbkFields, channels: &ChannelTable)
+    -> TsbkEvent
+{
+    // Grant updates share the layout of grants.
+    let f = tsbk::UnitVoiceGrant::new(tsbk);
+
+    TsbkEvent::new(name, json!({
+        "freq": channels.rx_freq(f.channel()),
+        "dest": f.dest_unit(),
+        "src": f.src_unit(),
+    }))
+}
+
+/// Create a telephone interconnect grant event.
+fn phone_grant_event(name: &'static str, p: &[u8], channels: &ChannelTable)
+    -> TsbkEvent
+{
+    TsbkEvent::new(name, json!({
+        "opts": opts(p[0]),
+        "freq": freq(channels, &p[1..3]),
+        "timer": be16(&p[3..5]),
+        "unit": be24(&p[5..8]),
+    }))
+}
+
+/// Create an event for a packet addressed from one unit to another.
+fn unit_event(name: &'static str, p: &[u8]) -> TsbkEvent {
+    TsbkEvent::new(name, json!({
+        "dest": be24(&p[2..5]),
+        "src": be24(&p[5..8]),
+    }))
+}
+
+/// Create an event for a queued or deny response.
+fn response_event(name: &'static str, p: &[u8]) -> TsbkEvent {
+    TsbkEvent::new(name, json!({
+        "service": p[0] & 0x3F,
+        "reason": p[1],
+        "unit": be24(&p[5..8]),
+    }))
+}
+
+/// Create an event carrying only the payload in hex.
+fn opaque_event(name: &'static str, p: &[u8]) -> TsbkEvent {
+    TsbkEvent::new(name, json!({
+        "payload": vendor::hex(p),
+    }))
+}
+
+/// Create a group voice grant update event.
+fn update_event(tg: Option<u16>, freq: Option<u32>) -> TsbkEvent {
+    TsbkEvent::group("groupGrantUpdate", tg, json!({
+        "talkgroup": tg,
+        "freq": freq,
+    }))
+}
+
+/// Create an event for the VHF/UHF or TDMA channel identifier update variant.
+fn iden_event(opcode: u8, p: &[u8]) -> TsbkEvent {
+    let (kind, bandwidth, sign, offset) = match opcode {
+        // VHF/UHF variant with a coded bandwidth and 13-bit offset.
+        0x34 => ("vu", match bits(p, 4, 4) {
+            0x4 => Some(6250),
+            0x5 => Some(12500),
+            _ => None,
+        }, bits(p, 8, 1), bits(p, 9, 13)),
+        // TDMA variant with a channel type in place of bandwidth.
+        _ => ("tdma", None, bits(p, 8, 1), bits(p, 9, 13)),
+    };
+
+    let offset = offset as i64 * 250000;
+
+    TsbkEvent::new("idenUpdate", json!({
+        "kind": kind,
+        "id": bits(p, 0, 4),
+        "channelType": if opcode == 0x33 { Some(bits(p, 4, 4)) } else { None },
+        "bandwidth": bandwidth,
+        // A set sign bit indicates the transmit frequency is above the base.
+        "offset": if sign == 1 { offset } else { -offset },
+        "spacing": bits(p, 22, 10) * 125,
+        "base": bits(p, 32, 32) as u64 * 5,
+    }))
+}
+
+/// Create a time and date announcement event.
+fn time_date_event(p: &[u8]) -> TsbkEvent {
+    let minutes = bits(p, 4, 4) as i32 * 60 + bits(p, 8, 1) as i32 * 30;
+
+    TsbkEvent::new("timeDate", json!({
+        "date": if bits(p, 0, 1) == 1 {
+            Some(format!("{:04}-{:02}-{:02}", bits(p, 25, 13), bits(p, 16, 4),
+                         bits(p, 20, 5)))
+        } else {
+            None
+        },
+        "time": if bits(p, 1, 1) == 1 {
+            Some(format!("{:02}:{:02}:{:02}", bits(p, 40, 5), bits(p, 45, 6),
+                         bits(p, 51, 6)))
+        } else {
+            None
+        },
+        // A set sign bit indicates local time is behind UTC.
+        "offset": if bits(p, 2, 1) == 0 {
+            None
+        } else if bits(p, 3, 1) == 1 {
+            Some(-minutes)
+        } else {
+            Some(minutes)
+        },
+    }))
+}
+
+/// Decode the service options in the given byte.
+fn opts(b: u8) -> Value {
+    json!({
+        "emergency": b >> 7 == 1,
+        "encrypted": b >> 6 & 1 == 1,
+        "duplex": b >> 5 & 1 == 1,
+        "packet": b >> 4 & 1 == 1,
+        "priority": b & 0b111,
+    })
+}
+
+/// Extract the given number of bits (up to 32) starting at the given bit offset, with
+/// bit 0 being the MSB of the first byte.
+fn bits(p: &[u8], start: usize, len: usize) -> u32 {
+    (start..start + len).fold(0, |v, i| {
+        v << 1 | (p[i / 8] >> (7 - i % 8) & 1) as u32
+    })
+}
+
+/// Parse a 16-bit big-endian value.
+fn be16(b: &[u8]) -> u16 {
+    (b[0] as u16) << 8 | b[1] as u16
+}
+
+/// Parse a 24-bit big-endian value.
+fn be24(b: &[u8]) -> u32 {
+    (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32
+}
+
+/// Build a single-block TSBK with a valid CRC from the given manufacturer ID, opcode,
+/// and payload.
+#[cfg(test)]
+pub fn build_tsbk(mfg: u8, opcode: u8, payload: [u8; 8]) -> [u8; 12] {
+    let mut buf = [0; 12];
+    buf[0] = 0x80 | opcode;
+    buf[1] = mfg;
+    buf[2..10].copy_from_slice(&payload);
+
+    let crc = !crc_ccitt(&buf[..10]);
+    buf[10] = (crc >> 8) as u8;
+    buf[11] = crc as u8;
+
+    buf
+}
+
+/// Compute the CRC-CCITT of the given bytes.
+#[cfg(test)]
+fn crc_ccitt(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |crc, &b| {
+        (0..8).fold(crc ^ (b as u16) << 8, |crc, _| {
+            if crc & 0x8000 == 0 { crc << 1 } else { crc << 1 ^ 0x1021 }
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use channels::ChannelConfig;
+
+    fn channels() -> ChannelTable {
+        ChannelTable::new(vec![
+            ChannelConfig {
+                id: 1,
+                base: 851000000,
+                spacing: 12500,
+                offset: -45000000,
+                bandwidth: 12500,
+                fixed: false,
+            },
+        ])
+    }
+
+    #[test]
+    fn test_grants() {
+        let c = channels();
+
+        // Emergency call on talkgroup 100, channel 1-100.
+        let p = [0x80, 0x10, 0x64, 0x00, 0x64, 0x00, 0x12, 0x34];
+        let e = decode(TsbkFields::new(build_tsbk(0, 0x00, p)), &c);
+        assert_eq!(e.len(), 1);
+        assert_eq!(e[0].name, "groupGrant");
+        assert_eq!(e[0].talkgroup, Some(100));
+        assert_eq!(e[0].payload["freq"].as_u64(), Some(852250000));
+        assert_eq!(e[0].payload["opts"]["emergency"].as_bool(), Some(true));
+        assert_eq!(e[0].payload["unit"].as_u64(), Some(0x1234));
+
+        // Talkgroups 10 and 20 on channels 1-100 and 1-101.
+        let p = [0x10, 0x64, 0x00, 0x0A, 0x10, 0x65, 0x00, 0x14];
+        let e = decode(TsbkFields::new(build_tsbk(0, 0x02, p)), &c);
+        assert_eq!(e.len(), 2);
+        assert_eq!(e[0].name, "groupGrantUpdate");
+        assert_eq!(e[0].talkgroup, Some(10));
+        assert_eq!(e[0].payload["freq"].as_u64(), Some(852250000));
+        assert_eq!(e[1].name, "groupGrantUpdate");
+        assert_eq!(e[1].talkgroup, Some(20));
+        assert_eq!(e[1].payload["freq"].as_u64(), Some(852262500));
+
+        // Talkgroup 30 on channel 1-100, with inbound channel 1-200.
+        let p = [0x80, 0x00, 0x10, 0x64, 0x10, 0xC8, 0x00, 0x1E];
+        let e = decode(TsbkFields::new(build_tsbk(0, 0x03, p)), &c);
+        assert_eq!(e.len(), 1);
+        assert_eq!(e[0].name, "groupGrantUpdate");
+        assert_eq!(e[0].talkgroup, Some(30));
+        assert_eq!(e[0].payload["freq"].as_u64(), Some(852250000));
+        assert_eq!(e[0].payload["opts"]["emergency"].as_bool(), Some(true));
+
+        let p = [0x10, 0x64, 0x00, 0x00, 0x01, 0x00, 0x00, 0x02];
+        let e = decode(TsbkFields::new(build_tsbk(0, 0x06, p)), &c);
+        assert_eq!(e[0].name, "unitGrantUpdate");
+        assert_eq!(e[0].payload["dest"].as_u64(), Some(1));
+        assert_eq!(e[0].payload["src"].as_u64(), Some(2));
+    }
+
+    #[test]
+    fn test_responses() {
+        let c = channels();
+
+        let p = [0x80 | 0x04, 0, 0, 0, 0, 0x00, 0x00, 0x07];
+        let e = decode(TsbkFields::new(build_tsbk(0, 0x20, p)), &c);
+        assert_eq!(e[0].name, "ackResponse");
+        assert_eq!(e[0].payload["service"].as_u64(), Some(0x04));
+        assert_eq!(e[0].payload["unit"].as_u64(), Some(7));
+
+        let p = [0x00, 0x54, 0, 0, 0, 0x00, 0x00, 0x07];
+        let e = decode(TsbkFields::new(build_tsbk(0, 0x27, p)), &c);
+        assert_eq!(e[0].name, "denyResponse");
+        assert_eq!(e[0].payload["service"].as_u64(), Some(0x00));
+        assert_eq!(e[0].payload["reason"].as_u64(), Some(0x54));
+        assert_eq!(e[0].payload["unit"].as_u64(), Some(7));
+
+        // Accepted affiliation of unit 7 to talkgroup 100.
+        let p = [0x00, 0x00, 0x00, 0x00, 0x64, 0x00, 0x00, 0x07];
+        let t = TsbkFields::new(build_tsbk(0, 0x28, p));
+        assert_eq!(decode(t, &c)[0].talkgroup, Some(100));
+        assert_eq!(affiliation(t), Some((100, 7)));
+
+        let p = [0x02, 0x00, 0x00, 0x00, 0x64, 0x00, 0x00, 0x07];
+        let t = TsbkFields::new(build_tsbk(0, 0x28, p));
+        assert_eq!(affiliation(t), None);
+    }
+
+    #[test]
+    fn test_secondary_control() {
+        // RFSS 1, site 2, on channels 1-100 and 1-101 with service classes 0x70 and 0x50.
+        let p = [0x01, 0x02, 0x10, 0x64, 0x70, 0x10, 0x65, 0x50];
+
+        let e = decode(TsbkFields::new(build_tsbk(0, 0x29, p)), &channels());
+        assert_eq!(e[0].name, "secondaryControl");
+        assert_eq!(e[0].payload["rfss"].as_u64(), Some(1));
+        assert_eq!(e[0].payload["site"].as_u64(), Some(2));
+        assert_eq!(e[0].payload["channels"][0]["freq"].as_u64(), Some(852250000));
+        assert_eq!(e[0].payload["channels"][0]["services"].as_u64(), Some(0x70));
+        assert_eq!(e[0].payload["channels"][1]["freq"].as_u64(), Some(852262500));
+        assert_eq!(e[0].payload["channels"][1]["services"].as_u64(), Some(0x50));
+    }
+
+    #[test]
+    fn test_time_date() {
+        // 2026-10-18 13:45:30, 5 hours behind UTC.
+        let p = [0xF5, 0x00, 0xA9, 0x1F, 0xA8, 0x6D, 0xAF, 0x00];
+
+        let e = decode(TsbkFields::new(build_tsbk(0, 0x35, p)), &channels());
+        assert_eq!(e[0].name, "timeDate");
+        assert_eq!(e[0].payload["date"].as_str(), Some("2026-10-18"));
+        assert_eq!(e[0].payload["time"].as_str(), Some("13:45:30"));
+        assert_eq!(e[0].payload["offset"].as_i64(), Some(-300));
+    }
+
+    #[test]
+    fn test_bits() {
+        let p = [0b10110010, 0b01111111, 0x12, 0x34];
+        assert_eq!(bits(&p, 0, 1), 1);
+        assert_eq!(bits(&p, 0, 4), 0b1011);
+        assert_eq!(bits(&p, 4, 8), 0b00100111);
+        assert_eq!(bits(&p, 16, 16), 0x1234);
+        assert_eq!(bits(&p, 0, 32), 0xB27F1234);
+    }
+
+    #[test]
+    fn test_iden() {
+        // ID 3, 12.5kHz, +5MHz offset, 12.5kHz spacing, 136MHz base.
+        let base: u32 = 136000000 / 5;
+        let p = [
+            0x35, 0x80, 0x50, 0x64,
+            (base >> 24) as u8, (base >> 16) as u8, (base >> 8) as u8, base as u8,
+        ];
+
+        let e = iden_event(0x34, &p);
+        assert_eq!(e.name, "idenUpdate");
+        assert_eq!(e.payload["id"].as_u64(), Some(3));
+        assert_eq!(e.payload["bandwidth"].as_u64(), Some(12500));
+        assert_eq!(e.payload["offset"].as_i64(), Some(5000000));
+        assert_eq!(e.payload["spacing"].as_u64(), Some(12500));
+        assert_eq!(e.payload["base"].as_u64(), Some(136000000));
+    }
+}
@@ -0,0 +1,223 @@
+//! Database of network sites learned from control channel broadcasts.
+
+use p25::trunking::fields::SystemServices;
+
+use store::JsonStore;
+
+/// Fully-qualified system identity.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct SystemId {
+    /// Wide area communications network ID.
+    pub wacn: u32,
+    /// System ID within the network.
+    pub system: u16,
+}
+
+impl SystemId {
+    /// Get the identity of the given site within the system.
+    pub fn site(&self, rfss: u8, site: u8) -> SiteId {
+        SiteId {
+            sys: *self,
+            rfss: rfss,
+            site: site,
+        }
+    }
+}
+
+/// Fully-qualified site identity.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct SiteId {
+    /// System the site belongs to.
+    pub sys: SystemId,
+    /// RF subsystem ID within the system.
+    pub rfss: u8,
+    /// Site ID within the RF subsystem.
+    pub site: u8,
+}
+
+/// Services offered by a site.
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct SiteServices {
+    /// Whether the site's control channel also carries voice and data traffic.
+    pub composite: bool,
+    /// Whether the site is only providing backup control channel service.
+    pub backup: bool,
+    /// Whether the site offers data service.
+    pub data: bool,
+    /// Whether the site offers voice service.
+    pub voice: bool,
+    /// Whether the site offers unit registration.
+    pub registration: bool,
+    /// Whether the site offers authentication.
+    pub auth: bool,
+}
+
+impl From<SystemServices> for SiteServices {
+    fn from(s: SystemServices) -> Self {
+        SiteServices {
+            composite: s.is_composite(),
+            backup: s.is_backup(),
+            data: s.has_data(),
+            voice: s.has_voice(),
+            registration: s.has_registration(),
+            auth: s.has_auth(),
+        }
+    }
+}
+
+/// Everything known about a site.
+#[derive(Serialize, Deserialize)]
+pub struct Site {
+    /// Site ID.
+    pub id: u8,
+    /// Location registration area.
+    pub area: Option<u8>,
+    /// Primary control channel frequency (Hz).
+    #[serde(rename = "ctlFreq")]
+    pub ctlfreq: Option<u32>,
+    /// Alternate control channel frequencies (Hz).
+    pub alts: Vec<u32>,
+    /// Offered services.
+    pub services: Option<SiteServices>,
+    /// Time the site was last mentioned in a broadcast (Unix timestamp).
+    #[serde(rename = "lastSeen")]
+    pub last_seen: i64,
+}
+
+impl Site {
+    /// Record the given alternate control channel frequency (Hz).
+    pub fn add_alt(&mut self, freq: u32) {
+        if !self.alts.contains(&freq) {
+            self.alts.push(freq);
+        }
+    }
+}
+
+/// RF subsystem and its sites.
+#[derive(Serialize, Deserialize)]
+pub struct Rfss {
+    /// RF subsystem ID.
+    pub id: u8,
+    /// Sites in the RF subsystem.
+    pub sites: Vec<Site>,
+}
+
+/// System and its RF subsystems.
+#[derive(Serialize, Deserialize)]
+pub struct System {
+    /// System ID.
+    pub id: u16,
+    /// RF subsystems in the system.
+    pub rfss: Vec<Rfss>,
+}
+
+/// Wide area communications network and its systems.
+#[derive(Serialize, Deserialize)]
+pub struct Network {
+    /// Wide area communications network ID.
+    pub wacn: u32,
+    /// Systems in the network.
+    pub systems: Vec<System>,
+}
+
+/// Hierarchy of learned networks, systems, RF subsystems, and sites.
+#[derive(Default, Serialize, Deserialize)]
+pub struct SiteDb {
+    /// Learned networks.
+    pub networks: Vec<Network>,
+}
+
+impl SiteDb {
+    /// Get the entry for the given site, creating it if necessary, and mark it as seen
+    /// at the given time.
+    pub fn site(&mut self, id: SiteId, now: i64) -> &mut Site {
+        let net = find_or_insert(&mut self.networks, |n| n.wacn == id.sys.wacn, || {
+            Network {
+                wacn: id.sys.wacn,
+                systems: vec![],
+            }
+        });
+
+        let sys = find_or_insert(&mut net.systems, |s| s.id == id.sys.system, || System {
+            id: id.sys.system,
+            rfss: vec![],
+        });
+
+        let rfss = find_or_insert(&mut sys.rfss, |r| r.id == id.rfss, || Rfss {
+            id: id.rfss,
+            sites: vec![],
+        });
+
+        let site = find_or_insert(&mut rfss.sites, |s| s.id == id.site, || Site {
+            id: id.site,
+            area: None,
+            ctlfreq: None,
+            alts: vec![],
+            services: None,
+            last_seen: now,
+        });
+
+        site.last_seen = now;
+        site
+    }
+}
+
+/// Find the item matching the given predicate, or append a new one.
+fn find_or_insert<T, P, F>(items: &mut Vec<T>, pred: P, new: F) -> &mut T
+    where P: Fn(&T) -> bool, F: FnOnce() -> T
+{
+    let pos = items.iter().position(pred);
+
+    let idx = match pos {
+        Some(idx) => idx,
+        None => {
+            items.push(new());
+            items.len() - 1
+        },
+    };
+
+    &mut items[idx]
+}
+
+/// Site database with an optional backing file.
+pub type SiteStore = JsonStore<SiteDb>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_site_db() {
+        let mut db = SiteDb::default();
+
+        let sys = SystemId { wacn: 0xBEE00, system: 0x123 };
+        let a = sys.site(1, 2);
+        let b = sys.site(1, 3);
+        let c = SystemId { wacn: 0xBEE00, system: 0x456 }.site(1, 2);
+
+        assert!(db.networks.is_empty());
+
+        db.site(a, 10).ctlfreq = Some(851000000);
+        db.site(b, 20).add_alt(852000000);
+        db.site(b, 30).add_alt(852000000);
+        db.site(c, 40);
+
+        assert_eq!(db.networks.len(), 1);
+        assert_eq!(db.networks[0].systems.len(), 2);
+        assert_eq!(db.networks[0].systems[0].rfss.len(), 1);
+        assert_eq!(db.networks[0].systems[0].rfss[0].sites.len(), 2);
+
+        let s = &db.networks[0].systems[0].rfss[0].sites[0];
+        assert_eq!(s.id, 2);
+        assert_eq!(s.ctlfreq, Some(851000000));
+        assert_eq!(s.last_seen, 10);
+
+        let s = &db.networks[0].systems[0].rfss[0].sites[1];
+        assert_eq!(s.id, 3);
+        assert_eq!(&s.alts[..], &[852000000]);
+        assert_eq!(s.last_seen, 30);
+
+        assert_eq!(db.networks[0].systems[1].id, 0x456);
+        assert_eq!(db.networks[0].systems[1].rfss[0].sites[0].id, 2);
+    }
+}
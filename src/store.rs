@@ -0,0 +1,112 @@
+//! Databases persisted as JSON files.
+
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std;
+
+use serde::{Deserialize, Serialize};
+use serde_json;
+
+/// Minimum time (sec) between writes of a database file.
+const SAVE_INTERVAL: i64 = 30;
+
+/// Database with an optional backing file.
+pub struct JsonStore<T> {
+    /// Current database.
+    db: T,
+    /// File to persist the database to.
+    path: Option<PathBuf>,
+    /// Description of the database for error messages.
+    desc: &'static str,
+    /// Whether the database has been modified since it was last saved.
+    dirty: bool,
+    /// Time the database was last saved (Unix timestamp).
+    saved: i64,
+}
+
+impl<T: Default + Serialize + Deserialize> JsonStore<T> {
+    /// Create a new `JsonStore` for the database with the given description, backed by
+    /// the given file, loading any previously saved database.
+    pub fn open(path: Option<PathBuf>, desc: &'static str) -> std::io::Result<Self> {
+        let db = match path {
+            Some(ref p) if p.exists() => {
+                let f = BufReader::new(try!(File::open(p)));
+
+                try!(serde_json::from_reader(f).map_err(|_| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidData,
+                                        format!("invalid {}", desc))
+                }))
+            },
+            _ => T::default(),
+        };
+
+        Ok(JsonStore {
+            db: db,
+            path: path,
+            desc: desc,
+            dirty: false,
+            saved: 0,
+        })
+    }
+
+    /// Get the current database.
+    pub fn db(&self) -> &T {
+        &self.db
+    }
+
+    /// Get the current database for modification.
+    pub fn db_mut(&mut self) -> &mut T {
+        self.dirty = true;
+        &mut self.db
+    }
+
+    /// Modify the database with the given function, which returns whether it changed
+    /// anything.
+    pub fn update<F: FnOnce(&mut T) -> bool>(&mut self, f: F) {
+        if f(&mut self.db) {
+            self.dirty = true;
+        }
+    }
+
+    /// Save the database if it has been modified and the save interval has elapsed as
+    /// of the given time.
+    pub fn flush(&mut self, now: i64) {
+        if now - self.saved < SAVE_INTERVAL {
+            return;
+        }
+
+        self.flush_now(now);
+    }
+
+    /// Save the database if it has been modified, regardless of the save interval.
+    pub fn flush_now(&mut self, now: i64) {
+        if !self.dirty {
+            return;
+        }
+
+        if let Some(ref path) = self.path {
+            if let Err(e) = save(&self.db, path) {
+                warn!("unable to save {}: {}", self.desc, e);
+            }
+        }
+
+        self.dirty = false;
+        self.saved = now;
+    }
+}
+
+/// Atomically write the given database to the given file.
+fn save<T: Serialize>(db: &T, path: &Path) -> std::io::Result<()> {
+    let tmp = path.with_extension("tmp");
+
+    {
+        let mut f = BufWriter::new(try!(File::create(&tmp)));
+
+        try!(serde_json::to_writer_pretty(&mut f, db)
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other,
+                                             "unable to serialize database")));
+    }
+
+    fs::rename(&tmp, path)
+}
@@ -0,0 +1,326 @@
+//! Event streaming to subscribers.
+
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use fnv::FnvBuildHasher;
+use serde::Serialize;
+use serde_json;
+use uhttp_json_api::HttpResult;
+use uhttp_sse::SseMessage;
+use uhttp_status::StatusCode;
+
+use http;
+use ws::{self, Frame, FrameReader, Opcode};
+
+/// Event serialized for streaming to subscribers.
+pub struct HubMessage {
+    /// Sequence number of the event, if it's part of the event history.
+    pub id: Option<u64>,
+    /// Event type name.
+    pub event: &'static str,
+    /// Talkgroup the event pertains to, if any.
+    pub talkgroup: Option<u16>,
+    /// Serialized JSON event.
+    pub json: String,
+}
+
+/// Collects the messages generated from a hub event.
+#[derive(Default)]
+pub struct MessageBuf(Vec<HubMessage>);
+
+impl MessageBuf {
+    /// Add an event with the given type and payload.
+    pub fn push<T: Serialize>(&mut self, event: &'static str, payload: T) {
+        self.add(event, None, payload);
+    }
+
+    /// Add an event with the given type and payload that pertains to the given
+    /// talkgroup.
+    pub fn push_group<T: Serialize>(&mut self, event: &'static str, tg: u16, payload: T) {
+        self.add(event, Some(tg), payload);
+    }
+
+    fn add<T: Serialize>(&mut self, event: &'static str, tg: Option<u16>, payload: T) {
+        self.0.push(HubMessage {
+            id: None,
+            event: event,
+            talkgroup: tg,
+            json: serde_json::to_string(&SerdeEvent::new(event, payload))
+                .expect("unable to serialize event"),
+        });
+    }
+
+    /// Get the collected messages.
+    pub fn messages(&self) -> &[HubMessage] {
+        &self.0[..]
+    }
+
+    /// Take ownership of the collected messages.
+    pub fn into_messages(self) -> Vec<HubMessage> {
+        self.0
+    }
+}
+
+impl HubMessage {
+    /// Check if the message should be kept in the event history.
+    ///
+    /// Frequent measurement events are superseded by the state snapshot, and raw vendor
+    /// packets and periodic control channel broadcasts are too frequent to be useful,
+    /// so replaying them would only push more useful events out of the history.
+    pub fn replayable(&self) -> bool {
+        match self.event {
+            "sigPower" | "updateStats" | "vendorTsbk" | "rawPacket" => false,
+            "groupGrantUpdate" | "idenUpdate" | "systemServices" | "timeDate" |
+                "secondaryControl" => false,
+            _ => true,
+        }
+    }
+
+    /// Check if the message is only streamed to subscribers that request its type.
+    ///
+    /// Undecoded vendor packets are frequent and only of use for protocol analysis.
+    pub fn opt_in(&self) -> bool {
+        self.event == "vendorTsbk"
+    }
+}
+
+#[derive(Serialize)]
+struct SerdeEvent<T: Serialize> {
+    event: &'static str,
+    payload: T,
+}
+
+impl<T: Serialize> SerdeEvent<T> {
+    pub fn new(event: &'static str, payload: T) -> Self {
+        SerdeEvent {
+            event: event,
+            payload: payload,
+        }
+    }
+}
+
+/// Selects which events are streamed to a subscriber.
+#[derive(Default)]
+pub struct EventFilter {
+    /// Event types to stream, or all types if `None`.
+    events: Option<HashSet<String, FnvBuildHasher>>,
+    /// Talkgroups to stream events for, or all talkgroups if `None`.
+    ///
+    /// Events that don't pertain to a talkgroup are unaffected.
+    talkgroups: Option<HashSet<u16, FnvBuildHasher>>,
+}
+
+impl EventFilter {
+    /// Parse a filter from the given request query string.
+    ///
+    /// The `events` parameter holds a comma-separated list of event types, and the `tg`
+    /// parameter holds a comma-separated list of talkgroup IDs.
+    pub fn from_query(query: Option<&str>) -> HttpResult<Self> {
+        let mut filt = EventFilter::default();
+
+        let query = match query {
+            Some(q) => q,
+            None => return Ok(filt),
+        };
+
+        for pair in http::query_pairs(query) {
+            let (key, val) = pair?;
+
+            match &key[..] {
+                "events" => {
+                    filt.events = Some(val.split(',')
+                        .filter(|s| !s.is_empty())
+                        .map(|s| s.to_string())
+                        .collect());
+                },
+                "tg" => {
+                    let mut tgs = HashSet::default();
+
+                    for s in val.split(',').filter(|s| !s.is_empty()) {
+                        tgs.insert(s.parse().map_err(|_| StatusCode::BadRequest)?);
+                    }
+
+                    filt.talkgroups = Some(tgs);
+                },
+                _ => return Err(StatusCode::BadRequest),
+            }
+        }
+
+        Ok(filt)
+    }
+
+    /// Check if the given message should be streamed.
+    pub fn passes(&self, m: &HubMessage) -> bool {
+        let requested = match self.events {
+            Some(ref events) => events.contains(m.event),
+            None => !m.opt_in(),
+        };
+
+        if !requested {
+            return false;
+        }
+
+        match (&self.talkgroups, m.talkgroup) {
+            (&Some(ref tgs), Some(tg)) => tgs.contains(&tg),
+            _ => true,
+        }
+    }
+}
+
+/// Protocol used to stream events to a subscriber.
+enum Protocol {
+    /// Server-sent events.
+    Sse,
+    /// WebSocket with state for reassembling received frames.
+    WebSocket(FrameReader),
+}
+
+/// Connection subscribed to receive events.
+pub struct Subscriber {
+    /// Underlying connection.
+    stream: TcpStream,
+    /// Events requested by the subscriber.
+    filter: EventFilter,
+    /// Streaming protocol.
+    proto: Protocol,
+}
+
+impl Subscriber {
+    /// Create a new SSE `Subscriber` over the given stream with the given event filter.
+    pub fn new(stream: TcpStream, filter: EventFilter) -> Self {
+        Subscriber {
+            stream: stream,
+            filter: filter,
+            proto: Protocol::Sse,
+        }
+    }
+
+    /// Create a new WebSocket `Subscriber` over the given stream with the given event
+    /// filter.
+    pub fn websocket(stream: TcpStream, filter: EventFilter) -> Self {
+        Subscriber {
+            stream: stream,
+            filter: filter,
+            proto: Protocol::WebSocket(FrameReader::default()),
+        }
+    }
+
+    /// Send the given message if it passes the subscriber's filter.
+    pub fn send(&mut self, m: &HubMessage) -> Result<(), ()> {
+        if !self.filter.passes(m) {
+            return Ok(());
+        }
+
+        if let Protocol::WebSocket(_) = self.proto {
+            return self.send_frame(Opcode::Text, m.json.as_bytes());
+        }
+
+        let mut msg = SseMessage::new(&mut self.stream);
+
+        if let Some(id) = m.id {
+            let mut field = msg.id().map_err(|_| ())?;
+            write!(field, "{}", id).map_err(|_| ())?;
+        }
+
+        let mut data = msg.data().map_err(|_| ())?;
+
+        data.write_all(m.json.as_bytes()).map_err(|_| ())
+    }
+
+    /// Send a WebSocket frame with the given opcode and payload.
+    pub fn send_frame(&mut self, opcode: Opcode, payload: &[u8]) -> Result<(), ()> {
+        ws::write_frame(&mut self.stream, opcode, payload).map_err(|_| ())
+    }
+
+    /// Read pending bytes from a WebSocket subscriber and collect any completed frames.
+    ///
+    /// This performs a single read, so it should only be called when the stream is
+    /// known to be readable. Return `Err(())` if the connection was closed or the
+    /// client violated the protocol.
+    pub fn recv_frames(&mut self) -> Result<Vec<Frame>, ()> {
+        let mut buf = [0; 4096];
+
+        let size = match self.stream.read(&mut buf) {
+            Ok(0) | Err(_) => return Err(()),
+            Ok(size) => size,
+        };
+
+        let reader = match self.proto {
+            Protocol::WebSocket(ref mut r) => r,
+            Protocol::Sse => return Err(()),
+        };
+
+        reader.feed(&buf[..size]);
+
+        let mut frames = vec![];
+
+        while let Some(f) = try!(reader.next_frame()) {
+            frames.push(f);
+        }
+
+        Ok(frames)
+    }
+}
+
+impl AsRawFd for Subscriber {
+    fn as_raw_fd(&self) -> RawFd {
+        self.stream.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_event_filter() {
+        let mut b = MessageBuf::default();
+        b.push("ctlFreq", 851000000);
+        b.push_group("talkGroup", 100, 100);
+        b.push_group("talkGroup", 300, 300);
+        b.push("sigPower", -40.0);
+
+        let m = b.messages();
+
+        let f = EventFilter::from_query(None).unwrap();
+        assert!(m.iter().all(|m| f.passes(m)));
+
+        let f = EventFilter::from_query(Some("events=talkGroup,ctlFreq")).unwrap();
+        assert!(f.passes(&m[0]));
+        assert!(f.passes(&m[1]));
+        assert!(f.passes(&m[2]));
+        assert!(!f.passes(&m[3]));
+
+        let f = EventFilter::from_query(Some("tg=100,200")).unwrap();
+        assert!(f.passes(&m[0]));
+        assert!(f.passes(&m[1]));
+        assert!(!f.passes(&m[2]));
+        assert!(f.passes(&m[3]));
+
+        let f = EventFilter::from_query(Some("events=talkGroup&tg=300")).unwrap();
+        assert!(!f.passes(&m[0]));
+        assert!(!f.passes(&m[1]));
+        assert!(f.passes(&m[2]));
+        assert!(!f.passes(&m[3]));
+
+        assert!(EventFilter::from_query(Some("tg=abc")).is_err());
+        assert!(EventFilter::from_query(Some("foo=bar")).is_err());
+
+        let f = EventFilter::from_query(Some("events=talkGroup%2CctlFreq")).unwrap();
+        assert!(f.passes(&m[0]));
+        assert!(!f.passes(&m[3]));
+
+        // Vendor packets are only streamed when requested.
+        let mut b = MessageBuf::default();
+        b.push("vendorTsbk", 0);
+        let m = b.messages();
+
+        assert!(!EventFilter::from_query(None).unwrap().passes(&m[0]));
+        assert!(!EventFilter::from_query(Some("tg=100")).unwrap().passes(&m[0]));
+        let f = EventFilter::from_query(Some("events=vendorTsbk")).unwrap();
+        assert!(f.passes(&m[0]));
+    }
+}
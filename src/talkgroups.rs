@@ -2,76 +2,247 @@
 
 use std::collections::hash_map::HashMap;
 use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use std;
 
 use fnv::FnvBuildHasher;
 use p25::voice::crypto::CryptoAlgorithm;
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use consts::BASEBAND_SAMPLE_RATE;
+use patch::{PatchMap, Regroup};
+use schedule::{LocalTime, Schedule};
+use tgstats::TalkgroupStats;
+
+/// Time (sec) after its most recent grant that a talkgroup's traffic channel is still
+/// trusted to carry the talkgroup, and after which its call is considered over.
+const TRAFFIC_SECS: usize = 5;
 
 /// Maps talkgroups to associated encryption algorithm.
 pub type GroupCryptoMap = HashMap<u16, CryptoAlgorithm, FnvBuildHasher>;
 
+/// Voice call that can be followed.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Call {
+    /// Call to the given talkgroup.
+    Group(u16),
+    /// Private call from the first unit to the second.
+    Unit(u32, u32),
+}
+
+impl Default for Call {
+    fn default() -> Self {
+        Call::Group(0)
+    }
+}
+
 /// Collects, prioritizes, filters, and selects talkgroups.
 #[derive(Default)]
 pub struct TalkgroupSelection {
-    /// Current set of candidate talkgroups.
-    cur: Vec<u16>,
-    /// Subset of `cur` talkgroups that can preempt a conversation.
-    cur_preempt: Vec<u16>,
-    /// Channel frequency associated with each candidate talkgroup.
-    channels: HashMap<u16, u32, FnvBuildHasher>,
+    /// Current set of candidate calls.
+    cur: Vec<Call>,
+    /// Subset of `cur` calls that can preempt a conversation.
+    cur_preempt: Vec<Call>,
+    /// Channel frequency associated with each candidate call.
+    channels: HashMap<Call, u32, FnvBuildHasher>,
     /// Set of talkgroups that have been observed to be encrypted.
     encrypted: GroupCryptoMap,
     /// Set of talkgroups that can preempt a conversation.
     preempt: HashSet<u16, FnvBuildHasher>,
+    /// Ranges and masks of talkgroups that can preempt a conversation.
+    preempt_patterns: Vec<TalkgroupPattern>,
     /// User-set included/excluded talkgroups.
     filter: Filter,
+    /// Talkgroup that all others are ignored in favor of, if any.
+    hold: Option<u16>,
+    /// Remaining time (baseband samples) before the hold is released, if it's timed.
+    hold_left: Option<usize>,
+    /// Active scan list, if any.
+    scanlist: Option<ScanList>,
+    /// Whether each entry of the active scan list is in effect, or `None` if schedules
+    /// haven't been evaluated since the list was activated.
+    scheduled: Option<Vec<bool>>,
+    /// Remaining time (baseband samples) each temporarily locked out talkgroup is
+    /// excluded from selection.
+    lockouts: HashMap<u16, usize, FnvBuildHasher>,
+    /// Talkgroup whose call was skipped, if any, and the time (`elapsed` samples) it was
+    /// last granted. It's excluded from selection until its call ends.
+    skipped: Option<(u16, usize)>,
+    /// Most recent traffic channel frequency granted to each talkgroup, regardless of
+    /// filtering, and the time (`elapsed` samples) of the grant.
+    traffic: HashMap<u16, (u32, usize), FnvBuildHasher>,
+    /// Running time (baseband samples) used to age traffic channel grants.
+    elapsed: usize,
+    /// User-set private call following.
+    units: UnitFilter,
+    /// Talkgroups with an emergency call in progress.
+    emergencies: HashSet<u16, FnvBuildHasher>,
+    /// Whether emergency calls preempt other calls and bypass the user filter.
+    emergency_preempt: bool,
+    /// Current talkgroup patches.
+    patches: PatchMap,
+    /// Long-running talkgroup activity statistics.
+    stats: TalkgroupStats,
     /// Talkgroup selection features.
     feats: TalkgroupFeatures,
 }
 
 impl TalkgroupSelection {
-    /// Record the given elapsed amount of baseband samples.
-    pub fn record_elapsed(&mut self, samples: usize) {
+    /// Record the given elapsed amount of baseband samples, returning whether a timed
+    /// hold was released.
+    pub fn record_elapsed(&mut self, samples: usize) -> bool {
         self.feats.record_elapsed(samples);
+        self.elapsed = self.elapsed.wrapping_add(samples);
+
+        if !self.lockouts.is_empty() {
+            self.lockouts.retain(|_, left| if *left > samples {
+                *left -= samples;
+                true
+            } else {
+                false
+            });
+        }
+
+        let left = match self.hold_left {
+            Some(left) => left,
+            None => return false,
+        };
+
+        if left > samples {
+            self.hold_left = Some(left - samples);
+            return false;
+        }
+
+        debug!("releasing hold on talkgroup {:?}", self.hold);
+        self.set_hold(None);
+
+        true
     }
 
     /// Consider the given talkgroup for the current set of candidate talkgroups.
     pub fn add_talkgroup(&mut self, tg: u16, freq: u32) {
-        if self.encrypted.contains_key(&tg) || self.filter.excluded(tg) {
+        self.traffic.insert(tg, (freq, self.elapsed));
+
+        if self.encrypted.contains_key(&tg) {
+            return;
+        }
+
+        let emergency = self.emergency_preempt && self.emergencies.contains(&tg);
+
+        // A supergroup carries the filtering and preemption of its members.
+        let groups = self.patches.members(tg);
+
+        if !emergency && groups.iter().all(|&g| self.filter.excluded(g)) {
+            return;
+        }
+
+        if !emergency && self.lockouts.contains_key(&tg) {
             return;
         }
 
-        self.feats.add(tg);
+        match self.skipped {
+            // Each grant soon after the previous one continues the skipped call.
+            Some((skipped, t)) if skipped == tg && self.elapsed.wrapping_sub(t) <
+                TRAFFIC_SECS * BASEBAND_SAMPLE_RATE as usize =>
+            {
+                self.skipped = Some((tg, self.elapsed));
+
+                if !emergency {
+                    return;
+                }
+            },
+            Some((skipped, _)) if skipped == tg => self.skipped = None,
+            _ => {},
+        }
 
-        if self.channels.insert(tg, freq).is_some() {
+        if !emergency && self.hold.map_or(false, |h| !groups.contains(&h)) {
             return;
         }
 
-        debug!("collecting talkgroup {}", tg);
+        // FIXME: non-lexical borrowing
+        let preempt = emergency || groups.iter().any(|&g| self.preempts(g));
+        self.add_call(Call::Group(tg), freq, preempt);
+    }
+
+    /// Check if the given talkgroup can preempt a conversation.
+    fn preempts(&self, tg: u16) -> bool {
+        self.preempt.contains(&tg) || self.preempt_patterns.iter().any(|p| p.matches(tg))
+    }
+
+    /// Consider the given private call from the first unit to the second for the
+    /// current set of candidates.
+    pub fn add_private(&mut self, src: u32, dest: u32, freq: u32) {
+        if self.hold.is_some() || !self.units.allows(src, dest) {
+            return;
+        }
+
+        self.add_call(Call::Unit(src, dest), freq, false);
+    }
+
+    /// Add the given call and its traffic channel frequency (Hz) to the candidates.
+    fn add_call(&mut self, call: Call, freq: u32, preempt: bool) {
+        self.feats.add(call);
+
+        if self.channels.insert(call, freq).is_some() {
+            return;
+        }
+
+        debug!("collecting {:?}", call);
+
+        self.cur.push(call);
+
+        if preempt {
+            self.cur_preempt.push(call);
+        }
+    }
 
-        self.cur.push(tg);
+    /// Select a call from the set of candidate non-preempting calls.
+    ///
+    /// If a call is available, return `Some((call, freq))`, where `freq` is the traffic
+    /// channel center frequency (Hz). Otherwise, return `None` if no calls are
+    /// available.
+    pub fn select_idle(&mut self) -> Option<(Call, u32)> {
+        debug!("selecting from {} calls", self.cur.len());
 
-        if self.preempt.contains(&tg) {
-            self.cur_preempt.push(tg);
+        // A held talkgroup is taken as soon as it's available, without scoring.
+        if let Some(tg) = self.hold {
+            if self.channels.contains_key(&Call::Group(tg)) {
+                return Some(self.select_tg(Call::Group(tg)));
+            }
         }
+
+        self.feats.max_score(&self.cur, &self.patches, &self.stats, &self.filter)
+            .map(|tg| self.select_tg(tg))
     }
 
-    /// Select a talkgroup from the set of candidate non-preempting talkgroups.
+    /// Select a call from the set of candidate preempting calls.
     ///
-    /// If a talkgroup is available, return `Some((tg, freq))`, where `tg` is the
-    /// talkgroup ID and `freq` is the traffic channel center frequency (Hz). Otherwise,
-    /// return `None` if no talkgroups are available.
-    pub fn select_idle(&mut self) -> Option<(u16, u32)> {
-        debug!("selecting from {} talkgroups", self.cur.len());
-        self.feats.max_score(&self.cur).map(|tg| self.select_tg(tg))
+    /// If a call is available, return `Some((call, freq))`, where `freq` is the traffic
+    /// channel center frequency (Hz). Otherwise, return `None` if no calls are
+    /// available.
+    pub fn select_preempt(&mut self) -> Option<(Call, u32)> {
+        self.feats.max_score(&self.cur_preempt, &self.patches, &self.stats,
+                             &self.filter)
+            .map(|tg| self.select_tg(tg))
     }
 
-    /// Select a talkgroup from the set of candidate preempting talkgroups.
+    /// Select the given talkgroup if it has a preempting emergency call among the
+    /// candidates.
     ///
-    /// If a talkgroup is available, return `Some((tg, freq))`, where `tg` is the
-    /// talkgroup ID and `freq` is the traffic channel center frequency (Hz). Otherwise,
-    /// return `None` if no talkgroups are available.
-    pub fn select_preempt(&mut self) -> Option<(u16, u32)> {
-        self.feats.max_score(&self.cur_preempt).map(|tg| self.select_tg(tg))
+    /// If so, return `Some((call, freq))`, where `freq` is the traffic channel center
+    /// frequency (Hz). Otherwise, return `None`.
+    pub fn select_emergency(&mut self, tg: u16) -> Option<(Call, u32)> {
+        let call = Call::Group(tg);
+
+        if !self.emergency_preempt || !self.emergencies.contains(&tg) ||
+            !self.cur_preempt.contains(&call)
+        {
+            return None;
+        }
+
+        Some(self.select_tg(call))
     }
 
     /// Record that the given talkgroup is encrypted.
@@ -80,9 +251,248 @@ impl TalkgroupSelection {
         self.encrypted.insert(tg, alg);
     }
 
-    /// Finalize selection of the given talkgroup.
-    fn select_tg(&mut self, tg: u16) -> (u16, u32) {
-        debug!("using talkgroup {}", tg);
+    /// Mark the talkgroups in the given map, previously learned on the current system,
+    /// as encrypted.
+    pub fn load_encrypted(&mut self, encrypted: GroupCryptoMap) {
+        debug!("loaded {} encrypted talkgroups", encrypted.len());
+        self.encrypted.extend(encrypted);
+    }
+
+    /// Forget that the given talkgroup, or all talkgroups if `None`, is encrypted.
+    pub fn clear_encrypted(&mut self, tg: Option<u16>) {
+        match tg {
+            Some(tg) => { self.encrypted.remove(&tg); },
+            None => self.encrypted.clear(),
+        }
+    }
+
+    /// Update talkgroup patches with the given regroup message.
+    pub fn update_patch(&mut self, r: &Regroup) {
+        self.patches.update(r);
+    }
+
+    /// Record whether the given talkgroup has an emergency call in progress, returning
+    /// whether the emergency is newly seen.
+    pub fn record_emergency(&mut self, tg: u16, emergency: bool) -> bool {
+        if emergency {
+            self.emergencies.insert(tg)
+        } else {
+            self.emergencies.remove(&tg);
+            false
+        }
+    }
+
+    /// Set whether emergency calls preempt other calls and bypass the user filter.
+    pub fn set_emergency_preempt(&mut self, preempt: bool) {
+        self.emergency_preempt = preempt;
+    }
+
+    /// Set the weight of talkgroup activity when scoring talkgroups, which is zero
+    /// (disabled) by default.
+    pub fn set_activity_weight(&mut self, weight: f32) {
+        self.feats.weights.activity = weight;
+    }
+
+    /// Get the long-running talkgroup activity statistics for updating.
+    pub fn stats_mut(&mut self) -> &mut TalkgroupStats {
+        &mut self.stats
+    }
+
+    /// Replace the talkgroup activity statistics, such as with ones saved by a previous
+    /// run.
+    pub fn set_stats(&mut self, stats: TalkgroupStats) {
+        self.stats = stats;
+    }
+
+    /// Check if emergency calls preempt other calls.
+    pub fn emergency_preempt(&self) -> bool {
+        self.emergency_preempt
+    }
+
+    /// Only consider the given talkgroup for selection, or consider all talkgroups if
+    /// `None`.
+    pub fn set_hold(&mut self, tg: Option<u16>) {
+        debug!("holding talkgroup {:?}", tg);
+
+        self.hold = tg;
+        self.hold_left = None;
+        self.clear_candidates();
+    }
+
+    /// Only consider the given talkgroup for selection until the given time (baseband
+    /// samples) has elapsed.
+    pub fn set_hold_timeout(&mut self, tg: u16, samples: usize) {
+        self.set_hold(Some(tg));
+        self.hold_left = Some(samples);
+    }
+
+    /// Exclude the given talkgroup from selection until the given time (baseband
+    /// samples) has elapsed.
+    pub fn lockout(&mut self, tg: u16, samples: usize) {
+        debug!("locking out talkgroup {} for {} samples", tg, samples);
+        self.lockouts.insert(tg, samples);
+    }
+
+    /// Exclude the given talkgroup from selection until its current call ends.
+    pub fn skip(&mut self, tg: u16) {
+        debug!("skipping call on talkgroup {}", tg);
+
+        self.skipped = Some((tg, self.elapsed));
+        self.cur.retain(|&c| c != Call::Group(tg));
+        self.cur_preempt.retain(|&c| c != Call::Group(tg));
+    }
+
+    /// Get the currently held talkgroup, if any.
+    pub fn hold(&self) -> Option<u16> {
+        self.hold
+    }
+
+    /// Select the given talkgroup immediately on its most recently granted traffic
+    /// channel, bypassing filtering and scoring.
+    ///
+    /// Return `Some((call, freq))` if the talkgroup has recently been granted a traffic
+    /// channel and `None` otherwise.
+    pub fn follow(&mut self, tg: u16) -> Option<(Call, u32)> {
+        let freq = match self.traffic.get(&tg) {
+            Some(&(f, t)) if self.elapsed.wrapping_sub(t) <
+                TRAFFIC_SECS * BASEBAND_SAMPLE_RATE as usize => f,
+            _ => return None,
+        };
+
+        debug!("following {} immediately", tg);
+
+        self.clear_candidates();
+        self.feats.select(Call::Group(tg));
+
+        Some((Call::Group(tg), freq))
+    }
+
+    /// Replace the user talkgroup filter.
+    ///
+    /// Scheduled entries aren't in effect until schedules are evaluated with
+    /// `update_schedule`.
+    pub fn set_filter(&mut self, f: Filter) {
+        self.filter = f;
+        self.clear_candidates();
+    }
+
+    /// Replace the talkgroup filter, priorities, and preemption with the given scan
+    /// list.
+    ///
+    /// Scheduled entries aren't in effect until schedules are evaluated with
+    /// `update_schedule`.
+    pub fn set_scanlist(&mut self, list: &ScanList) {
+        self.scanlist = Some(list.clone());
+        self.scheduled = None;
+        self.apply_scanlist();
+    }
+
+    /// Evaluate the schedules of the user filter and active scan list at the given local
+    /// time, returning whether any entry came into or went out of effect.
+    pub fn update_schedule(&mut self, t: LocalTime) -> bool {
+        let filter = self.filter.update_schedule(t);
+
+        if filter {
+            self.clear_candidates();
+        }
+
+        let active = match self.scanlist {
+            Some(ref list) => list.talkgroups.iter()
+                .map(|e| e.schedule.as_ref().map_or(true, |s| s.active(t)))
+                .collect::<Vec<_>>(),
+            None => return filter,
+        };
+
+        if self.scheduled.as_ref() == Some(&active) {
+            return filter;
+        }
+
+        self.scheduled = Some(active);
+        self.apply_scanlist();
+
+        true
+    }
+
+    /// Get each scheduled talkgroup in the active scan list and user filter with whether
+    /// its entry is in effect.
+    pub fn schedule_state(&self) -> Vec<(TalkgroupPattern, bool)> {
+        let mut state = match self.scanlist {
+            Some(ref list) => list.talkgroups.iter().enumerate()
+                .filter(|&(_, e)| e.schedule.is_some())
+                .map(|(i, e)| (e.tg, self.entry_active(i)))
+                .collect(),
+            None => vec![],
+        };
+
+        state.extend(self.filter.schedule_state());
+        state
+    }
+
+    /// Check if the scan list entry at the given index is in effect.
+    fn entry_active(&self, idx: usize) -> bool {
+        match self.scheduled {
+            Some(ref s) => s[idx],
+            None => self.scanlist.as_ref()
+                .map_or(false, |l| l.talkgroups[idx].schedule.is_none()),
+        }
+    }
+
+    /// Rebuild the talkgroup filter, priorities, and preemption from the entries of the
+    /// active scan list that are in effect.
+    fn apply_scanlist(&mut self) {
+        {
+            let list = match self.scanlist {
+                Some(ref l) => l,
+                None => return,
+            };
+
+            let entries = list.talkgroups.iter().enumerate()
+                .filter(|&(i, _)| self.entry_active(i))
+                .map(|(_, e)| e)
+                .collect::<Vec<_>>();
+
+            // Single talkgroups are kept separately for fast lookup.
+            let (singles, patterns): (Vec<&ScanEntry>, Vec<&ScanEntry>) =
+                entries.iter().partition(|e| e.tg.single().is_some());
+
+            self.filter = if list.all {
+                Filter::default()
+            } else {
+                Filter {
+                    exclude: false,
+                    filt: singles.iter().filter_map(|e| e.tg.single()).collect(),
+                    patterns: patterns.iter().map(|e| e.tg).collect(),
+                    entries: vec![],
+                    scheduled: None,
+                }
+            };
+
+            self.feats.prios = singles.iter()
+                .filter_map(|e| e.tg.single().map(|tg| (tg, e.priority)))
+                .collect();
+            self.feats.prio_patterns = patterns.iter().map(|e| (e.tg, e.priority))
+                .collect();
+
+            self.preempt = singles.iter().filter(|e| e.preempt)
+                .filter_map(|e| e.tg.single())
+                .collect();
+            self.preempt_patterns = patterns.iter().filter(|e| e.preempt)
+                .map(|e| e.tg)
+                .collect();
+        }
+
+        self.clear_candidates();
+    }
+
+    /// Replace the user private call filter.
+    pub fn set_units(&mut self, f: UnitFilter) {
+        self.units = f;
+        self.clear_candidates();
+    }
+
+    /// Finalize selection of the given call.
+    fn select_tg(&mut self, tg: Call) -> (Call, u32) {
+        debug!("using {:?}", tg);
 
         let freq = self.channels[&tg];
 
@@ -102,7 +512,11 @@ impl TalkgroupSelection {
     /// Clear state related to the current talkgroup site.
     pub fn clear_state(&mut self) {
         self.clear_candidates();
+        self.traffic.clear();
+        self.skipped = None;
         self.encrypted.clear();
+        self.emergencies.clear();
+        self.patches.clear();
         self.feats.reset();
     }
 }
@@ -114,12 +528,15 @@ impl TalkgroupSelection {
 struct TalkgroupFeatures {
     /// Current baseband sample counter since the last talkgroup selection.
     elapsed: usize,
-    /// Timestamp when each talkgroup in `cur` was added.
-    age: HashMap<u16, usize, FnvBuildHasher>,
-    /// Set of recently-visited talkgroups and associated visit timestamp.
-    recent: u16,
+    /// Timestamp when each call in `cur` was added.
+    age: HashMap<Call, usize, FnvBuildHasher>,
+    /// Most recently selected call.
+    recent: Call,
     /// User-set talkgroup priorities.
     pub prios: HashMap<u16, f32, FnvBuildHasher>,
+    /// User-set priorities of talkgroup ranges and masks, used for talkgroups without
+    /// an individual priority.
+    pub prio_patterns: Vec<(TalkgroupPattern, f32)>,
     /// User-set weights for each feature used when scoring each talkgroup.
     pub weights: FeatureWeights,
 }
@@ -130,13 +547,13 @@ impl TalkgroupFeatures {
         self.elapsed = self.elapsed.wrapping_add(samples);
     }
 
-    /// Add the given talkgroup to the set of candidates (or update its age.)
-    pub fn add(&mut self, tg: u16) {
+    /// Add the given call to the set of candidates (or update its age.)
+    pub fn add(&mut self, tg: Call) {
         self.age.insert(tg, self.elapsed);
     }
 
-    /// Update state to reflect that the given talkgroup was selected.
-    pub fn select(&mut self, tg: u16) {
+    /// Update state to reflect that the given call was selected.
+    pub fn select(&mut self, tg: Call) {
         self.age.clear();
         self.recent = tg;
         self.elapsed = 0;
@@ -144,7 +561,7 @@ impl TalkgroupFeatures {
 
     /// Reset talkgroup-related state.
     pub fn reset(&mut self) {
-        self.select(0);
+        self.select(Call::default());
     }
 
     /// Retrieve the oldest age over all talkgroups.
@@ -152,10 +569,27 @@ impl TalkgroupFeatures {
         self.elapsed.wrapping_sub(self.age.values().cloned().min().unwrap_or(0))
     }
 
-    /// Find the talkgroup with the highest score in the given candidate talkgroups.
+    /// Get the user priority of the given talkgroup.
+    fn prio(&self, tg: u16) -> f32 {
+        if let Some(&p) = self.prios.get(&tg) {
+            return p;
+        }
+
+        self.prio_patterns.iter()
+            .find(|&&(ref p, _)| p.matches(tg))
+            .map_or(1.0, |&(_, prio)| prio)
+    }
+
+    /// Find the call with the highest score in the given candidate calls.
     ///
-    /// Each talkgroup must have been previously recorded with the `add` method.
-    pub fn max_score(&self, groups: &[u16]) -> Option<u16> {
+    /// Each call must have been previously recorded with the `add` method. A supergroup
+    /// takes the highest priority of its members in the given patches, talkgroup
+    /// activity is taken from the given statistics, and priorities in the given user
+    /// filter override the others.
+    pub fn max_score(&self, groups: &[Call], patches: &PatchMap, stats: &TalkgroupStats,
+                     filter: &Filter)
+        -> Option<Call>
+    {
         let oldest = self.oldest() as f32;
 
         // If the oldest talkgroup has no age, then none of the others will either, so
@@ -167,10 +601,23 @@ impl TalkgroupFeatures {
             let age = 1.0 - self.elapsed.wrapping_sub(self.age[&tg]) as f32 * mul;
             // Recent talkgroup gets a reward.
             let recent = if tg == self.recent { 1.0 } else { 0.0 };
+            // Private calls have the default priority.
+            let prio = match tg {
+                Call::Group(g) => patches.members(g).iter()
+                    .map(|&m| filter.priority(m).unwrap_or_else(|| self.prio(m)))
+                    .fold(std::f32::NEG_INFINITY, f32::max),
+                Call::Unit(..) => 1.0,
+            };
+            // Busier talkgroups score higher.
+            let activity = match tg {
+                Call::Group(g) => stats.activity(g),
+                Call::Unit(..) => 0.0,
+            };
 
-            self.prios.get(&tg).unwrap_or(&1.0) * self.weights.prio +
+            prio * self.weights.prio +
             age * self.weights.age +
-            recent * self.weights.recent
+            recent * self.weights.recent +
+            activity * self.weights.activity
         };
 
         groups.iter().cloned()
@@ -187,6 +634,8 @@ pub struct FeatureWeights {
     age: f32,
     /// Weight of recently-selected talkgroup reward.
     recent: f32,
+    /// Weight of long-running talkgroup activity.
+    activity: f32,
 }
 
 impl Default for FeatureWeights {
@@ -195,10 +644,194 @@ impl Default for FeatureWeights {
             prio: 1.0,
             age: 1.0,
             recent: 1.0,
+            activity: 0.0,
+        }
+    }
+}
+
+/// Scan lists by name.
+pub type ScanLists = HashMap<String, ScanList>;
+
+/// Named set of talkgroups to follow, with their priorities and preemption.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ScanList {
+    /// Whether talkgroups not in the list are also followed, at the default priority.
+    #[serde(default)]
+    all: bool,
+    /// Talkgroups in the list.
+    #[serde(default)]
+    talkgroups: Vec<ScanEntry>,
+}
+
+/// Talkgroup in a scan list.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ScanEntry {
+    /// Talkgroup ID, range, or mask.
+    tg: TalkgroupPattern,
+    /// Priority used when scoring the talkgroup.
+    #[serde(default = "default_priority")]
+    priority: f32,
+    /// Whether calls on the talkgroup preempt other calls.
+    #[serde(default)]
+    preempt: bool,
+    /// When the entry is in effect, or always if `None`.
+    #[serde(default)]
+    schedule: Option<Schedule>,
+}
+
+fn default_priority() -> f32 { 1.0 }
+
+/// Matches talkgroups individually, by range, or by bitmask.
+///
+/// This is written as a talkgroup ID, an inclusive range like `"1000-1099"`, a hex
+/// pattern with wildcard digits like `"0x12xx"`, or a value and mask like
+/// `"0x1200/0xff00"`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TalkgroupPattern {
+    /// Single talkgroup.
+    Single(u16),
+    /// Talkgroups from the first to the second, inclusive.
+    Range(u16, u16),
+    /// Talkgroups matching the first value in the bits set in the second.
+    Mask(u16, u16),
+}
+
+impl TalkgroupPattern {
+    /// Check if the given talkgroup matches the pattern.
+    pub fn matches(&self, tg: u16) -> bool {
+        match *self {
+            TalkgroupPattern::Single(t) => tg == t,
+            TalkgroupPattern::Range(start, end) => start <= tg && tg <= end,
+            TalkgroupPattern::Mask(val, mask) => tg & mask == val,
+        }
+    }
+
+    /// Get the talkgroup if the pattern matches only a single one.
+    pub fn single(&self) -> Option<u16> {
+        match *self {
+            TalkgroupPattern::Single(t) => Some(t),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for TalkgroupPattern {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let s = s.trim();
+
+        if let Some(idx) = s.find('-') {
+            let (start, end) = (parse_id(&s[..idx])?, parse_id(&s[idx + 1..])?);
+
+            return if start <= end {
+                Ok(TalkgroupPattern::Range(start, end))
+            } else {
+                Err(())
+            };
+        }
+
+        if let Some(idx) = s.find('/') {
+            let mask = parse_id(&s[idx + 1..])?;
+            return Ok(TalkgroupPattern::Mask(parse_id(&s[..idx])? & mask, mask));
+        }
+
+        let digits = match hex_digits(s) {
+            Some(d) if d.contains(|c| c == 'x' || c == 'X') => d,
+            _ => return parse_id(s).map(TalkgroupPattern::Single),
+        };
+
+        if digits.len() > 4 {
+            return Err(());
+        }
+
+        let mut val = 0;
+        let mut mask = 0;
+
+        // Each wildcard matches any value of its hex digit.
+        for c in digits.chars() {
+            val <<= 4;
+            mask <<= 4;
+
+            if c == 'x' || c == 'X' {
+                continue;
+            }
+
+            val |= c.to_digit(16).ok_or(())? as u16;
+            mask |= 0xF;
+        }
+
+        Ok(TalkgroupPattern::Mask(val, mask))
+    }
+}
+
+impl fmt::Display for TalkgroupPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TalkgroupPattern::Single(t) => write!(f, "{}", t),
+            TalkgroupPattern::Range(start, end) => write!(f, "{}-{}", start, end),
+            TalkgroupPattern::Mask(val, mask) =>
+                write!(f, "0x{:04x}/0x{:04x}", val, mask),
         }
     }
 }
 
+impl Serialize for TalkgroupPattern {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            TalkgroupPattern::Single(t) => s.serialize_u16(t),
+            _ => s.serialize_str(&self.to_string()),
+        }
+    }
+}
+
+impl Deserialize for TalkgroupPattern {
+    fn deserialize<D: Deserializer>(d: D) -> Result<Self, D::Error> {
+        struct PatternVisitor;
+
+        impl Visitor for PatternVisitor {
+            type Value = TalkgroupPattern;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "talkgroup ID, range, or mask")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                if v > std::u16::MAX as u64 {
+                    Err(E::custom("talkgroup ID out of range"))
+                } else {
+                    Ok(TalkgroupPattern::Single(v as u16))
+                }
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse().map_err(|_| E::custom("invalid talkgroup pattern"))
+            }
+        }
+
+        d.deserialize(PatternVisitor)
+    }
+}
+
+/// Get the digits of the given hex string without its `0x` prefix, if it has one.
+fn hex_digits(s: &str) -> Option<&str> {
+    if s.starts_with("0x") || s.starts_with("0X") {
+        Some(&s[2..])
+    } else {
+        None
+    }
+}
+
+/// Parse a decimal or `0x`-prefixed hex talkgroup ID.
+fn parse_id(s: &str) -> Result<u16, ()> {
+    let s = s.trim();
+
+    match hex_digits(s) {
+        Some(d) => u16::from_str_radix(d, 16).map_err(|_| ()),
+        None => s.parse().map_err(|_| ()),
+    }
+}
+
 /// Filters talkgroups with an include-by-default or exclude-by-default policy.
 #[derive(Serialize, Deserialize)]
 pub struct Filter {
@@ -207,6 +840,16 @@ pub struct Filter {
     exclude: bool,
     /// Included/excluded talkgroups.
     filt: HashSet<u16, FnvBuildHasher>,
+    /// Included/excluded talkgroup ranges and masks.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    patterns: Vec<TalkgroupPattern>,
+    /// Included/excluded talkgroups with a priority or schedule.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    entries: Vec<FilterEntry>,
+    /// Whether each entry is in effect, or `None` if schedules haven't been evaluated
+    /// since the filter was set.
+    #[serde(skip_serializing, skip_deserializing)]
+    scheduled: Option<Vec<bool>>,
 }
 
 impl Default for Filter {
@@ -215,6 +858,9 @@ impl Default for Filter {
         Filter {
             exclude: true,
             filt: HashSet::default(),
+            patterns: Vec::new(),
+            entries: Vec::new(),
+            scheduled: None,
         }
     }
 }
@@ -222,9 +868,87 @@ impl Default for Filter {
 impl Filter {
     /// Check if the given talkgroup is excluded from selection.
     pub fn excluded(&self, tg: u16) -> bool {
-        let filtered = self.filt.contains(&tg);
+        let filtered = self.filt.contains(&tg) ||
+            self.patterns.iter().any(|p| p.matches(tg)) ||
+            self.active_entry(tg).is_some();
         self.exclude && filtered || !self.exclude && !filtered
     }
+
+    /// Get the user priority of the given talkgroup, if an entry in effect sets one.
+    pub fn priority(&self, tg: u16) -> Option<f32> {
+        self.active_entry(tg).and_then(|e| e.priority)
+    }
+
+    /// Evaluate the schedules of the entries at the given local time, returning whether
+    /// any entry came into or went out of effect.
+    pub fn update_schedule(&mut self, t: LocalTime) -> bool {
+        let active = self.entries.iter()
+            .map(|e| e.schedule.as_ref().map_or(true, |s| s.active(t)))
+            .collect::<Vec<_>>();
+
+        if self.scheduled.as_ref() == Some(&active) {
+            return false;
+        }
+
+        self.scheduled = Some(active);
+
+        true
+    }
+
+    /// Get each scheduled talkgroup with whether its entry is in effect.
+    fn schedule_state(&self) -> Vec<(TalkgroupPattern, bool)> {
+        self.entries.iter().enumerate()
+            .filter(|&(_, e)| e.schedule.is_some())
+            .map(|(i, e)| (e.tg, self.entry_active(i)))
+            .collect()
+    }
+
+    /// Find the first entry in effect that matches the given talkgroup.
+    fn active_entry(&self, tg: u16) -> Option<&FilterEntry> {
+        self.entries.iter().enumerate()
+            .find(|&(i, e)| e.tg.matches(tg) && self.entry_active(i))
+            .map(|(_, e)| e)
+    }
+
+    /// Check if the entry at the given index is in effect.
+    fn entry_active(&self, idx: usize) -> bool {
+        match self.scheduled {
+            Some(ref s) => s[idx],
+            None => self.entries[idx].schedule.is_none(),
+        }
+    }
+}
+
+/// Talkgroup filter entry with an optional priority and schedule.
+#[derive(Serialize, Deserialize)]
+pub struct FilterEntry {
+    /// Talkgroup ID, range, or mask.
+    tg: TalkgroupPattern,
+    /// Priority used when scoring the talkgroup, overriding any scan list priority.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    priority: Option<f32>,
+    /// When the entry is in effect, or always if `None`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    schedule: Option<Schedule>,
+}
+
+/// Selects which private calls are followed.
+#[derive(Default, Deserialize)]
+pub struct UnitFilter {
+    /// Whether private calls are followed at all.
+    #[serde(default)]
+    follow: bool,
+    /// Units whose calls are followed, or empty to follow calls of all units.
+    #[serde(default)]
+    allow: HashSet<u32, FnvBuildHasher>,
+}
+
+impl UnitFilter {
+    /// Check if a private call between the given units should be followed.
+    pub fn allows(&self, src: u32, dest: u32) -> bool {
+        self.follow &&
+            (self.allow.is_empty() || self.allow.contains(&src) || self.allow.contains(&dest))
+    }
 }
 
 #[cfg(test)]
@@ -255,6 +979,58 @@ mod test {
         assert!(f.filt.contains(&1));
         assert!(f.filt.contains(&2));
         assert!(f.filt.contains(&3));
+
+        let f: Filter = serde_json::from_str(r#"{
+            "filt": [5],
+            "patterns": ["1000-1099", "0x12xx", 42],
+            "exclude": false
+        }"#).unwrap();
+        assert!(!f.excluded(5));
+        assert!(!f.excluded(1000));
+        assert!(!f.excluded(1099));
+        assert!(f.excluded(1100));
+        assert!(!f.excluded(0x1234));
+        assert!(f.excluded(0x1334));
+        assert!(!f.excluded(42));
+        assert!(f.excluded(43));
+
+        let j = serde_json::to_string(&f.patterns).unwrap();
+        assert_eq!(j, "[\"1000-1099\",\"0x1200/0xff00\",42]");
+
+        assert!(serde_json::from_str::<Filter>(
+            "{\"filt\": [], \"patterns\": [\"20-10\"], \"exclude\": true}").is_err());
+    }
+
+    #[test]
+    fn test_pattern() {
+        use self::TalkgroupPattern::*;
+
+        assert_eq!("42".parse(), Ok(Single(42)));
+        assert_eq!("0x2a".parse(), Ok(Single(42)));
+        assert_eq!(" 10 - 20 ".parse(), Ok(Range(10, 20)));
+        assert_eq!("0x10-0x1f".parse(), Ok(Range(16, 31)));
+        assert_eq!("0x12xx".parse(), Ok(Mask(0x1200, 0xFF00)));
+        assert_eq!("0xx2".parse(), Ok(Mask(0x02, 0x0F)));
+        assert_eq!("0x1234/0xff00".parse(), Ok(Mask(0x1200, 0xFF00)));
+        assert_eq!("4660/65280".parse(), Ok(Mask(0x1200, 0xFF00)));
+        assert_eq!("20-10".parse::<TalkgroupPattern>(), Err(()));
+        assert_eq!("0x12xxx".parse::<TalkgroupPattern>(), Err(()));
+        assert_eq!("0x1gxx".parse::<TalkgroupPattern>(), Err(()));
+        assert_eq!("70000".parse::<TalkgroupPattern>(), Err(()));
+        assert_eq!("abc".parse::<TalkgroupPattern>(), Err(()));
+
+        assert!(Range(10, 20).matches(10));
+        assert!(Range(10, 20).matches(20));
+        assert!(!Range(10, 20).matches(21));
+        assert!(Mask(0x02, 0x0F).matches(0x1232));
+        assert!(!Mask(0x02, 0x0F).matches(0x1233));
+
+        let mut feats = TalkgroupFeatures::default();
+        feats.prios.insert(1050, 3.0);
+        feats.prio_patterns.push((Range(1000, 1099), 2.0));
+        assert_eq!(feats.prio(1050), 3.0);
+        assert_eq!(feats.prio(1051), 2.0);
+        assert_eq!(feats.prio(1100), 1.0);
     }
 
     #[test]
@@ -272,7 +1048,7 @@ mod test {
         ts.add_talkgroup(44, 4);
         ts.record_elapsed(40);
         assert_eq!(ts.feats.oldest(), 100);
-        assert_eq!(ts.select_idle(), Some((44, 4)));
+        assert_eq!(ts.select_idle(), Some((Call::Group(44), 4)));
 
         ts.clear_state();
 
@@ -289,7 +1065,7 @@ mod test {
         ts.record_elapsed(40);
         assert_eq!(ts.feats.oldest(), 100);
         ts.feats.weights.age = -1.0;
-        assert_eq!(ts.select_idle(), Some((11, 1)));
+        assert_eq!(ts.select_idle(), Some((Call::Group(11), 1)));
     }
 
     #[test]
@@ -299,27 +1075,27 @@ mod test {
 
         // Test initial select.
         ts.add_talkgroup(10, 42);
-        assert_eq!(&ts.cur[..], &[10]);
+        assert_eq!(&ts.cur[..], &[Call::Group(10)]);
         assert!(ts.cur_preempt.is_empty());
         assert_eq!(ts.select_preempt(), None);
-        assert_eq!(ts.select_idle(), Some((10, 42)));
-        assert_eq!(ts.feats.recent, 10);
+        assert_eq!(ts.select_idle(), Some((Call::Group(10), 42)));
+        assert_eq!(ts.feats.recent, Call::Group(10));
         assert_eq!(ts.select_idle(), None);
-        assert_eq!(ts.feats.recent, 10);
+        assert_eq!(ts.feats.recent, Call::Group(10));
         assert!(ts.feats.age.is_empty());
         assert!(ts.channels.is_empty());
 
         // Test preempt talkgroup.
         ts.preempt.insert(20);
         ts.add_talkgroup(10, 800);
-        assert_eq!(&ts.cur[..], &[10]);
+        assert_eq!(&ts.cur[..], &[Call::Group(10)]);
         assert!(ts.cur_preempt.is_empty());
         ts.add_talkgroup(20, 200);
-        assert_eq!(&ts.cur[..], &[10, 20]);
-        assert_eq!(&ts.cur_preempt[..], &[20]);
-        assert_eq!(ts.feats.recent, 10);
-        assert_eq!(ts.select_preempt(), Some((20, 200)));
-        assert_eq!(ts.feats.recent, 20);
+        assert_eq!(&ts.cur[..], &[Call::Group(10), Call::Group(20)]);
+        assert_eq!(&ts.cur_preempt[..], &[Call::Group(20)]);
+        assert_eq!(ts.feats.recent, Call::Group(10));
+        assert_eq!(ts.select_preempt(), Some((Call::Group(20), 200)));
+        assert_eq!(ts.feats.recent, Call::Group(20));
         assert_eq!(ts.select_preempt(), None);
         assert_eq!(ts.select_idle(), None);
         assert!(ts.feats.age.is_empty());
@@ -343,17 +1119,307 @@ mod test {
         ts.add_talkgroup(30, 300);
         ts.add_talkgroup(40, 400);
         ts.add_talkgroup(50, 500);
-        assert_eq!(&ts.cur[..], &[10, 40, 50]);
+        assert_eq!(&ts.cur[..], &[Call::Group(10), Call::Group(40), Call::Group(50)]);
         assert!(ts.cur_preempt.is_empty());
         assert_eq!(ts.select_preempt(), None);
-        assert_eq!(ts.select_idle(), Some((40, 400)));
-        assert_eq!(ts.feats.recent, 40);
+        assert_eq!(ts.select_idle(), Some((Call::Group(40), 400)));
+        assert_eq!(ts.feats.recent, Call::Group(40));
         assert_eq!(ts.select_idle(), None);
         assert!(ts.feats.age.is_empty());
         assert!(ts.channels.is_empty());
 
         ts.clear_state();
         assert!(ts.encrypted.is_empty());
-        assert_eq!(ts.feats.recent, 0);
+        assert_eq!(ts.feats.recent, Call::Group(0));
+    }
+
+    #[test]
+    fn test_encrypted_persist() {
+        let mut ts = TalkgroupSelection::default();
+
+        let mut enc = GroupCryptoMap::default();
+        enc.insert(20, CryptoAlgorithm::Aes);
+        enc.insert(30, CryptoAlgorithm::Aes);
+        ts.load_encrypted(enc);
+        ts.add_talkgroup(20, 12);
+        ts.add_talkgroup(30, 13);
+        assert!(ts.cur.is_empty());
+
+        ts.clear_encrypted(Some(20));
+        ts.add_talkgroup(20, 12);
+        ts.add_talkgroup(30, 13);
+        assert_eq!(&ts.cur[..], &[Call::Group(20)]);
+
+        ts.clear_encrypted(None);
+        ts.add_talkgroup(30, 13);
+        assert_eq!(&ts.cur[..], &[Call::Group(20), Call::Group(30)]);
+    }
+
+    #[test]
+    fn test_private() {
+        use serde_json;
+
+        let mut ts = TalkgroupSelection::default();
+        ts.add_private(1, 2, 100);
+        assert!(ts.cur.is_empty());
+
+        let f: UnitFilter = serde_json::from_str("{\"follow\": true}").unwrap();
+        ts.set_units(f);
+        ts.add_talkgroup(10, 200);
+        ts.record_elapsed(10);
+        ts.add_private(1, 2, 100);
+        assert_eq!(&ts.cur[..], &[Call::Group(10), Call::Unit(1, 2)]);
+        assert_eq!(ts.select_idle(), Some((Call::Unit(1, 2), 100)));
+
+        let f: UnitFilter = serde_json::from_str("{\"follow\": true, \"allow\": [3]}")
+                                .unwrap();
+        ts.set_units(f);
+        ts.add_private(1, 2, 100);
+        ts.add_private(4, 3, 300);
+        assert_eq!(&ts.cur[..], &[Call::Unit(4, 3)]);
+
+        ts.set_hold(Some(10));
+        ts.add_private(4, 3, 300);
+        assert!(ts.cur.is_empty());
+    }
+
+    #[test]
+    fn test_emergency() {
+        let mut ts = TalkgroupSelection::default();
+        ts.filter.filt.insert(10);
+
+        assert!(ts.record_emergency(10, true));
+        assert!(!ts.record_emergency(10, true));
+        ts.add_talkgroup(10, 100);
+        assert!(ts.cur.is_empty());
+
+        ts.set_emergency_preempt(true);
+        ts.add_talkgroup(10, 100);
+        assert_eq!(&ts.cur_preempt[..], &[Call::Group(10)]);
+        assert_eq!(ts.select_preempt(), Some((Call::Group(10), 100)));
+
+        assert!(!ts.record_emergency(10, false));
+        ts.add_talkgroup(10, 100);
+        assert!(ts.cur.is_empty());
+        assert!(ts.record_emergency(10, true));
+
+        // Other preempting talkgroups don't take the place of the emergency.
+        ts.preempt.insert(20);
+        ts.add_talkgroup(20, 200);
+        assert_eq!(ts.select_emergency(20), None);
+        ts.add_talkgroup(10, 100);
+        assert_eq!(&ts.cur_preempt[..], &[Call::Group(20), Call::Group(10)]);
+        assert_eq!(ts.select_emergency(10), Some((Call::Group(10), 100)));
+        assert!(ts.cur_preempt.is_empty());
+    }
+
+    #[test]
+    fn test_patch() {
+        let mut ts = TalkgroupSelection::default();
+        ts.filter.exclude = false;
+        ts.filter.filt.insert(10);
+        ts.preempt.insert(10);
+        ts.feats.prios.insert(10, 100.0);
+
+        ts.add_talkgroup(500, 1000);
+        assert!(ts.cur.is_empty());
+
+        // Supergroup takes the filter, preemption, and priority of its members.
+        ts.update_patch(&Regroup::Add(500, vec![10, 20]));
+        ts.add_talkgroup(500, 1000);
+        ts.add_talkgroup(20, 3000);
+        assert_eq!(&ts.cur[..], &[Call::Group(500)]);
+        assert_eq!(&ts.cur_preempt[..], &[Call::Group(500)]);
+        assert_eq!(ts.select_preempt(), Some((Call::Group(500), 1000)));
+
+        ts.update_patch(&Regroup::Delete(500, vec![10]));
+        ts.add_talkgroup(500, 1000);
+        assert!(ts.cur.is_empty());
+    }
+
+    #[test]
+    fn test_activity() {
+        let mut ts = TalkgroupSelection::default();
+        ts.stats_mut().record_grant(11, 0);
+        ts.stats_mut().record_grant(11, 100);
+        ts.stats_mut().record_grant(22, 200);
+
+        ts.add_talkgroup(11, 1);
+        ts.add_talkgroup(22, 2);
+        assert_eq!(ts.select_idle(), Some((Call::Group(22), 2)));
+
+        ts.set_activity_weight(10.0);
+        ts.add_talkgroup(11, 1);
+        ts.add_talkgroup(22, 2);
+        ts.feats.recent = Call::default();
+        assert_eq!(ts.select_idle(), Some((Call::Group(11), 1)));
+    }
+
+    #[test]
+    fn test_hold() {
+        let mut ts = TalkgroupSelection::default();
+        ts.feats.prios.insert(20, 100.0);
+
+        ts.set_hold_timeout(10, 100);
+        assert_eq!(ts.hold(), Some(10));
+        ts.add_talkgroup(20, 200);
+        ts.add_talkgroup(10, 100);
+        assert_eq!(ts.select_idle(), Some((Call::Group(10), 100)));
+
+        assert!(!ts.record_elapsed(99));
+        assert_eq!(ts.hold(), Some(10));
+        assert!(ts.record_elapsed(1));
+        assert_eq!(ts.hold(), None);
+        assert!(!ts.record_elapsed(1000));
+
+        ts.add_talkgroup(10, 100);
+        ts.add_talkgroup(20, 200);
+        assert_eq!(ts.select_idle(), Some((Call::Group(20), 200)));
+
+        ts.filter.filt.insert(30);
+        ts.add_talkgroup(30, 300);
+        assert!(ts.cur.is_empty());
+        assert_eq!(ts.follow(30), Some((Call::Group(30), 300)));
+        assert_eq!(ts.follow(40), None);
+
+        // Grants from a call that has since ended aren't followed.
+        ts.record_elapsed(TRAFFIC_SECS * BASEBAND_SAMPLE_RATE as usize);
+        assert_eq!(ts.follow(30), None);
+    }
+
+    #[test]
+    fn test_lockout() {
+        let mut ts = TalkgroupSelection::default();
+
+        ts.lockout(10, 100);
+        ts.add_talkgroup(10, 100);
+        ts.add_talkgroup(20, 200);
+        assert_eq!(&ts.cur[..], &[Call::Group(20)]);
+
+        ts.record_elapsed(99);
+        ts.add_talkgroup(10, 100);
+        assert_eq!(&ts.cur[..], &[Call::Group(20)]);
+
+        ts.record_elapsed(1);
+        ts.add_talkgroup(10, 100);
+        assert_eq!(&ts.cur[..], &[Call::Group(20), Call::Group(10)]);
+
+        // Emergencies aren't locked out when they preempt.
+        ts.clear_state();
+        ts.set_emergency_preempt(true);
+        ts.lockout(30, 100);
+        ts.record_emergency(30, true);
+        ts.add_talkgroup(30, 300);
+        assert_eq!(&ts.cur[..], &[Call::Group(30)]);
+    }
+
+    #[test]
+    fn test_skip() {
+        let mut ts = TalkgroupSelection::default();
+        let gap = TRAFFIC_SECS * BASEBAND_SAMPLE_RATE as usize;
+
+        ts.add_talkgroup(10, 100);
+        ts.skip(10);
+        assert_eq!(ts.select_idle(), None);
+        ts.add_talkgroup(10, 100);
+        assert_eq!(ts.select_idle(), None);
+
+        // Grants continuing the call keep it skipped.
+        ts.record_elapsed(gap - 1);
+        ts.add_talkgroup(10, 100);
+        ts.record_elapsed(gap - 1);
+        ts.add_talkgroup(10, 100);
+        ts.add_talkgroup(20, 200);
+        assert_eq!(ts.select_idle(), Some((Call::Group(20), 200)));
+
+        // A grant after the call has ended starts a new call.
+        ts.record_elapsed(gap);
+        ts.add_talkgroup(10, 100);
+        assert_eq!(ts.select_idle(), Some((Call::Group(10), 100)));
+        assert_eq!(ts.skipped, None);
+    }
+
+    #[test]
+    fn test_scanlist() {
+        use serde_json;
+
+        let lists: ScanLists = serde_json::from_str(r#"{
+            "fire": {"talkgroups": [
+                {"tg": 10},
+                {"tg": 20, "priority": 10, "preempt": true}
+            ]},
+            "all": {"all": true}
+        }"#).unwrap();
+
+        let mut ts = TalkgroupSelection::default();
+
+        ts.set_scanlist(&lists["fire"]);
+        ts.add_talkgroup(10, 100);
+        ts.add_talkgroup(30, 300);
+        assert_eq!(&ts.cur[..], &[Call::Group(10)]);
+        assert!(ts.cur_preempt.is_empty());
+        ts.add_talkgroup(20, 200);
+        assert_eq!(&ts.cur_preempt[..], &[Call::Group(20)]);
+        assert_eq!(ts.select_idle(), Some((Call::Group(20), 200)));
+
+        ts.set_scanlist(&lists["all"]);
+        ts.add_talkgroup(20, 200);
+        ts.add_talkgroup(30, 300);
+        assert_eq!(&ts.cur[..], &[Call::Group(20), Call::Group(30)]);
+        assert!(ts.cur_preempt.is_empty());
+    }
+
+    #[test]
+    fn test_schedule() {
+        use schedule::Day;
+        use serde_json;
+
+        let list: ScanList = serde_json::from_str(r#"{"talkgroups": [
+            {"tg": 10},
+            {"tg": 20, "priority": 10, "schedule": {
+                "windows": [{"start": "08:00", "end": "17:00"}]
+            }}
+        ]}"#).unwrap();
+
+        let mut ts = TalkgroupSelection::default();
+        ts.set_scanlist(&list);
+        assert_eq!(ts.schedule_state(), vec![(TalkgroupPattern::Single(20), false)]);
+
+        let t = |minute| LocalTime { day: Day::Monday, minute: minute };
+
+        assert!(ts.update_schedule(t(600)));
+        assert!(!ts.update_schedule(t(601)));
+        assert_eq!(ts.schedule_state(), vec![(TalkgroupPattern::Single(20), true)]);
+        ts.add_talkgroup(10, 100);
+        ts.add_talkgroup(20, 200);
+        assert_eq!(ts.select_idle(), Some((Call::Group(20), 200)));
+
+        assert!(ts.update_schedule(t(1020)));
+        assert_eq!(ts.schedule_state(), vec![(TalkgroupPattern::Single(20), false)]);
+        ts.add_talkgroup(10, 100);
+        ts.add_talkgroup(20, 200);
+        assert_eq!(&ts.cur[..], &[Call::Group(10)]);
+
+        // Filter entries carry schedules and priorities outside a scan list.
+        let f: Filter = serde_json::from_str(r#"{
+            "exclude": false,
+            "filt": [10],
+            "entries": [{"tg": "20-29", "priority": 10, "schedule": {"days": ["tue"]}}]
+        }"#).unwrap();
+
+        let mut ts = TalkgroupSelection::default();
+        ts.set_filter(f);
+        assert_eq!(ts.schedule_state(), vec![(TalkgroupPattern::Range(20, 29), false)]);
+        assert!(ts.update_schedule(t(600)));
+        assert!(!ts.update_schedule(t(601)));
+        ts.add_talkgroup(10, 100);
+        ts.add_talkgroup(20, 200);
+        assert_eq!(&ts.cur[..], &[Call::Group(10)]);
+
+        assert!(ts.update_schedule(LocalTime { day: Day::Tuesday, minute: 600 }));
+        assert_eq!(ts.schedule_state(), vec![(TalkgroupPattern::Range(20, 29), true)]);
+        ts.add_talkgroup(10, 100);
+        ts.add_talkgroup(20, 200);
+        assert_eq!(ts.select_idle(), Some((Call::Group(20), 200)));
     }
 }
@@ -0,0 +1,218 @@
+//! Long-running talkgroup activity statistics.
+
+use std::collections::HashMap;
+
+use fnv::FnvBuildHasher;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use store::JsonStore;
+
+/// Time (seconds) within which repeated grants for the same talkgroup are merged into
+/// one grant.
+const GRANT_GAP: i64 = 10;
+
+/// Statistics of each talkgroup.
+type GroupMap = HashMap<u16, GroupStats, FnvBuildHasher>;
+
+/// Talkgroup statistics with an optional backing file.
+pub type StatsStore = JsonStore<TalkgroupStats>;
+
+/// Activity statistics for a single talkgroup.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct GroupStats {
+    /// Number of voice grants seen.
+    pub grants: u32,
+    /// Number of calls followed.
+    pub calls: u32,
+    /// Number of followed calls that turned out to be encrypted.
+    pub encrypted: u32,
+    /// Total time (seconds) spent following calls.
+    pub airtime: i64,
+    /// Time (Unix seconds) of the most recent grant or call, if any.
+    #[serde(rename = "lastActive")]
+    pub last_active: Option<i64>,
+    /// Number of grants seen in each hour of the day (UTC).
+    pub hourly: [u32; 24],
+}
+
+impl GroupStats {
+    /// Get the fraction of followed calls that were encrypted, if any were followed.
+    pub fn encrypted_fraction(&self) -> Option<f32> {
+        if self.calls == 0 {
+            None
+        } else {
+            Some(self.encrypted as f32 / self.calls as f32)
+        }
+    }
+}
+
+/// Change to talkgroup activity.
+#[derive(Copy, Clone)]
+pub enum StatsEvent {
+    /// Voice grant for the given talkgroup at the given time (Unix seconds).
+    Grant(u16, i64),
+    /// Call on the given talkgroup started being followed at the given time.
+    StartCall(u16, i64),
+    /// Call being followed, if any, ended at the given time.
+    EndCall(i64),
+    /// Followed call on the given talkgroup was encrypted.
+    Encrypted(u16),
+}
+
+/// Collects activity statistics for all talkgroups.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct TalkgroupStats {
+    /// Statistics for each talkgroup, serialized as talkgroup/statistics pairs.
+    #[serde(serialize_with = "ser_groups", deserialize_with = "de_groups")]
+    groups: GroupMap,
+    /// Talkgroup and start time (Unix seconds) of the call being followed, if any.
+    #[serde(skip_serializing, skip_deserializing)]
+    active: Option<(u16, i64)>,
+    /// Time (Unix seconds) of the most recent grant for each talkgroup.
+    #[serde(skip_serializing, skip_deserializing)]
+    granted: HashMap<u16, i64, FnvBuildHasher>,
+    /// Highest grant count over all talkgroups.
+    #[serde(rename = "maxGrants")]
+    max_grants: u32,
+}
+
+impl TalkgroupStats {
+    /// Get the statistics of all talkgroups.
+    pub fn groups(&self) -> &GroupMap {
+        &self.groups
+    }
+
+    /// Update the statistics with the given activity.
+    pub fn apply(&mut self, e: StatsEvent) {
+        match e {
+            StatsEvent::Grant(tg, now) => self.record_grant(tg, now),
+            StatsEvent::StartCall(tg, now) => self.start_call(tg, now),
+            StatsEvent::EndCall(now) => self.end_call(now),
+            StatsEvent::Encrypted(tg) => self.record_encrypted(tg),
+        }
+    }
+
+    /// Record a voice grant for the given talkgroup at the given time (Unix seconds).
+    ///
+    /// Grants are repeated throughout a call, so a grant within the merge gap of the
+    /// previous one for the same talkgroup isn't counted again.
+    pub fn record_grant(&mut self, tg: u16, now: i64) {
+        let prev = self.granted.insert(tg, now);
+        let s = self.groups.entry(tg).or_insert_with(GroupStats::default);
+
+        s.last_active = Some(now);
+
+        if prev.map_or(false, |t| now - t <= GRANT_GAP) {
+            return;
+        }
+
+        s.grants += 1;
+        s.hourly[(now / 3600 % 24) as usize] += 1;
+
+        if s.grants > self.max_grants {
+            self.max_grants = s.grants;
+        }
+    }
+
+    /// Record that a call on the given talkgroup started being followed at the given
+    /// time.
+    pub fn start_call(&mut self, tg: u16, now: i64) {
+        self.end_call(now);
+
+        let s = self.groups.entry(tg).or_insert_with(GroupStats::default);
+
+        s.calls += 1;
+        s.last_active = Some(now);
+
+        self.active = Some((tg, now));
+    }
+
+    /// Record that the call being followed, if any, ended at the given time.
+    pub fn end_call(&mut self, now: i64) {
+        let (tg, start) = match self.active.take() {
+            Some(a) => a,
+            None => return,
+        };
+
+        let s = self.groups.entry(tg).or_insert_with(GroupStats::default);
+
+        s.airtime += now - start;
+        s.last_active = Some(now);
+    }
+
+    /// Record that a followed call on the given talkgroup was encrypted.
+    pub fn record_encrypted(&mut self, tg: u16) {
+        self.groups.entry(tg).or_insert_with(GroupStats::default).encrypted += 1;
+    }
+
+    /// Get the grant activity of the given talkgroup relative to the busiest talkgroup,
+    /// from 0 to 1.
+    pub fn activity(&self, tg: u16) -> f32 {
+        if self.max_grants == 0 {
+            return 0.0;
+        }
+
+        self.groups.get(&tg).map_or(0.0, |s| s.grants as f32 / self.max_grants as f32)
+    }
+}
+
+fn ser_groups<S: Serializer>(g: &GroupMap, s: S) -> Result<S::Ok, S::Error> {
+    g.iter().collect::<Vec<_>>().serialize(s)
+}
+
+fn de_groups<D: Deserializer>(d: D) -> Result<GroupMap, D::Error> {
+    Vec::<(u16, GroupStats)>::deserialize(d).map(|g| g.into_iter().collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json;
+
+    #[test]
+    fn test_stats() {
+        let mut s = TalkgroupStats::default();
+        assert_eq!(s.activity(1), 0.0);
+
+        s.record_grant(1, 3600 * 5);
+        s.record_grant(1, 3600 * 5 + 60);
+        s.record_grant(2, 3600 * 30);
+        assert_eq!(s.activity(1), 1.0);
+        assert_eq!(s.activity(2), 0.5);
+        assert_eq!(s.activity(3), 0.0);
+        assert_eq!(s.groups()[&1].hourly[5], 2);
+        assert_eq!(s.groups()[&2].hourly[6], 1);
+
+        s.start_call(1, 100);
+        s.start_call(2, 130);
+        s.end_call(145);
+        s.end_call(200);
+        s.start_call(2, 300);
+        s.record_encrypted(2);
+        s.end_call(301);
+
+        assert_eq!(s.groups()[&1].calls, 1);
+        assert_eq!(s.groups()[&1].airtime, 30);
+        assert_eq!(s.groups()[&1].encrypted_fraction(), Some(0.0));
+        assert_eq!(s.groups()[&2].calls, 2);
+        assert_eq!(s.groups()[&2].airtime, 16);
+        assert_eq!(s.groups()[&2].last_active, Some(301));
+        assert_eq!(s.groups()[&2].encrypted_fraction(), Some(0.5));
+        assert_eq!(GroupStats::default().encrypted_fraction(), None);
+
+        // Repeated grants during a call are merged.
+        s.apply(StatsEvent::Grant(3, 1000));
+        s.apply(StatsEvent::Grant(3, 1005));
+        s.apply(StatsEvent::Grant(3, 1015));
+        assert_eq!(s.groups()[&3].grants, 1);
+        assert_eq!(s.groups()[&3].last_active, Some(1015));
+        s.apply(StatsEvent::Grant(3, 1030));
+        assert_eq!(s.groups()[&3].grants, 2);
+
+        let j = serde_json::to_string(&s).unwrap();
+        let s: TalkgroupStats = serde_json::from_str(&j).unwrap();
+        assert_eq!(s.groups()[&1].airtime, 30);
+        assert_eq!(s.groups()[&3].grants, 2);
+        assert_eq!(s.activity(1), 1.0);
+    }
+}
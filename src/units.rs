@@ -0,0 +1,202 @@
+//! Tracking of radio unit registrations, affiliations, and calls.
+
+use std::collections::{HashMap, VecDeque};
+
+use fnv::FnvBuildHasher;
+
+/// Maximum number of affiliated talkgroups kept per unit.
+const MAX_AFFILIATIONS: usize = 8;
+
+/// Maximum number of recent calls kept per unit.
+const MAX_CALLS: usize = 16;
+
+/// Time (seconds) within which repeated grants and link control for the same call are
+/// merged into one call.
+const CALL_GAP: i64 = 10;
+
+/// Interval (seconds) between checks for stale units.
+const EXPIRE_INTERVAL: i64 = 60;
+
+/// Call a unit keyed up on.
+#[derive(Copy, Clone, PartialEq, Eq, Serialize)]
+pub enum UnitCall {
+    /// Call to the given talkgroup.
+    #[serde(rename = "talkgroup")]
+    Group(u16),
+    /// Private call to the given unit.
+    #[serde(rename = "unit")]
+    Unit(u32),
+}
+
+/// Call with the time it was last seen.
+#[derive(Copy, Clone, Serialize)]
+pub struct CallRecord {
+    /// Destination of the call.
+    pub call: UnitCall,
+    /// Time (Unix seconds) the call started.
+    pub start: i64,
+    /// Time (Unix seconds) the call was last seen.
+    pub last: i64,
+}
+
+/// Activity of a single radio unit.
+#[derive(Clone, Default, Serialize)]
+pub struct UnitInfo {
+    /// Time (Unix seconds) of the most recent registration, if any.
+    pub registered: Option<i64>,
+    /// Time (Unix seconds) of the most recent deregistration, if any.
+    pub deregistered: Option<i64>,
+    /// Affiliated talkgroups, most recent last.
+    pub affiliations: Vec<u16>,
+    /// Recent calls, most recent last.
+    pub calls: VecDeque<CallRecord>,
+    /// Time (Unix seconds) of the most recent activity.
+    #[serde(rename = "lastSeen")]
+    pub last_seen: i64,
+}
+
+/// Tracks activity of radio units seen on the control and traffic channels.
+pub struct UnitTracker {
+    /// Activity of each unit.
+    units: HashMap<u32, UnitInfo, FnvBuildHasher>,
+    /// Time (seconds) without activity before a unit is forgotten, or zero to never
+    /// forget units.
+    expiry: i64,
+    /// Time (Unix seconds) of the most recent check for stale units.
+    checked: i64,
+}
+
+impl UnitTracker {
+    /// Create a new `UnitTracker` that forgets units after the given time (seconds)
+    /// without activity.
+    pub fn new(expiry: i64) -> Self {
+        UnitTracker {
+            units: HashMap::default(),
+            expiry: expiry,
+            checked: 0,
+        }
+    }
+
+    /// Get the activity of all tracked units.
+    pub fn units(&self) -> &HashMap<u32, UnitInfo, FnvBuildHasher> {
+        &self.units
+    }
+
+    /// Get the activity of the given unit, if it's tracked.
+    pub fn get(&self, unit: u32) -> Option<&UnitInfo> {
+        self.units.get(&unit)
+    }
+
+    /// Record that the given unit registered at the given time.
+    pub fn register(&mut self, unit: u32, now: i64) {
+        self.unit(unit, now).registered = Some(now);
+    }
+
+    /// Record that the given unit deregistered at the given time.
+    pub fn deregister(&mut self, unit: u32, now: i64) {
+        let u = self.unit(unit, now);
+
+        u.deregistered = Some(now);
+        u.affiliations.clear();
+    }
+
+    /// Record that the given unit affiliated to the given talkgroup at the given time.
+    pub fn affiliate(&mut self, unit: u32, tg: u16, now: i64) {
+        let u = self.unit(unit, now);
+
+        u.affiliations.retain(|&t| t != tg);
+
+        if u.affiliations.len() == MAX_AFFILIATIONS {
+            u.affiliations.remove(0);
+        }
+
+        u.affiliations.push(tg);
+    }
+
+    /// Record that the given unit keyed up on the given call at the given time.
+    pub fn record_call(&mut self, unit: u32, call: UnitCall, now: i64) {
+        let u = self.unit(unit, now);
+
+        if let Some(c) = u.calls.back_mut() {
+            if c.call == call && now - c.last <= CALL_GAP {
+                c.last = now;
+                return;
+            }
+        }
+
+        if u.calls.len() == MAX_CALLS {
+            u.calls.pop_front();
+        }
+
+        u.calls.push_back(CallRecord {
+            call: call,
+            start: now,
+            last: now,
+        });
+    }
+
+    /// Forget units without activity since the expiry time before the given time.
+    pub fn expire(&mut self, now: i64) {
+        if self.expiry == 0 || now - self.checked < EXPIRE_INTERVAL {
+            return;
+        }
+
+        self.checked = now;
+
+        let expiry = self.expiry;
+        self.units.retain(|_, u| now - u.last_seen < expiry);
+    }
+
+    /// Get the entry for the given unit, marking it as seen at the given time.
+    fn unit(&mut self, unit: u32, now: i64) -> &mut UnitInfo {
+        let u = self.units.entry(unit).or_insert_with(UnitInfo::default);
+        u.last_seen = now;
+        u
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_units() {
+        let mut t = UnitTracker::new(3600);
+
+        t.register(100, 1000);
+        t.affiliate(100, 1, 1001);
+        t.affiliate(100, 2, 1002);
+        t.affiliate(100, 1, 1003);
+        assert_eq!(t.get(100).unwrap().registered, Some(1000));
+        assert_eq!(t.get(100).unwrap().affiliations, vec![2, 1]);
+
+        t.record_call(100, UnitCall::Group(1), 1010);
+        t.record_call(100, UnitCall::Group(1), 1015);
+        t.record_call(100, UnitCall::Group(1), 1030);
+        t.record_call(100, UnitCall::Unit(200), 1031);
+
+        {
+            let calls = &t.get(100).unwrap().calls;
+            assert_eq!(calls.len(), 3);
+            assert_eq!(calls[0].start, 1010);
+            assert_eq!(calls[0].last, 1015);
+            assert!(calls[1].call == UnitCall::Group(1));
+            assert!(calls[2].call == UnitCall::Unit(200));
+        }
+
+        t.deregister(100, 1040);
+        assert_eq!(t.get(100).unwrap().deregistered, Some(1040));
+        assert!(t.get(100).unwrap().affiliations.is_empty());
+
+        t.register(200, 1050);
+        t.expire(4641);
+        assert!(t.get(100).is_none());
+        assert!(t.get(200).is_some());
+
+        // Checks are rate limited.
+        t.expire(4690);
+        assert!(t.get(200).is_some());
+        t.expire(4701);
+        assert!(t.get(200).is_none());
+    }
+}
@@ -0,0 +1,202 @@
+//! Decoding of manufacturer-specific trunking packets.
+
+use p25::trunking::fields::Channel;
+use p25::trunking::tsbk::TsbkFields;
+
+use patch::Regroup;
+
+/// Manufacturer ID of Motorola.
+pub const MFG_MOTOROLA: u8 = 0x90;
+/// Manufacturer ID of Harris.
+pub const MFG_HARRIS: u8 = 0xA4;
+
+/// Decoded manufacturer-specific trunking packet.
+#[derive(Clone)]
+pub enum VendorTsbk {
+    /// Talkgroup patch or regroup message.
+    Regroup(Regroup),
+    /// Base station identification with the given callsign and control channel.
+    BaseStationId(String, Channel),
+    /// Packet with an unknown manufacturer or opcode.
+    Unknown(u8, u8, [u8; 8]),
+}
+
+/// Decode the given manufacturer-specific trunking packet.
+pub fn decode(tsbk: &TsbkFields) -> VendorTsbk {
+    let known = match tsbk.mfg() {
+        MFG_MOTOROLA => decode_motorola(tsbk),
+        MFG_HARRIS => decode_harris(tsbk),
+        _ => None,
+    };
+
+    known.unwrap_or_else(|| {
+        let mut payload = [0; 8];
+        payload.copy_from_slice(&tsbk.payload()[..8]);
+
+        VendorTsbk::Unknown(tsbk.mfg(), tsbk.raw_opcode(), payload)
+    })
+}
+
+/// Decode a known Motorola opcode.
+fn decode_motorola(tsbk: &TsbkFields) -> Option<VendorTsbk> {
+    let p = tsbk.payload();
+
+    let r = match tsbk.raw_opcode() {
+        0x00 => Regroup::Add(be16(&p[0..2]), members(&p[2..8])),
+        0x01 => Regroup::Delete(be16(&p[0..2]), members(&p[2..8])),
+        0x02 => Regroup::Grant(be16(&p[3..5]), Channel::new(&p[1..3]), be24(&p[5..8])),
+        0x03 => Regroup::Update(vec![
+            (Channel::new(&p[0..2]), be16(&p[2..4])),
+            (Channel::new(&p[4..6]), be16(&p[6..8])),
+        ]),
+        0x0B => return Some(VendorTsbk::BaseStationId(callsign(&p[0..6]),
+                                                      Channel::new(&p[6..8]))),
+        _ => return None,
+    };
+
+    Some(VendorTsbk::Regroup(r))
+}
+
+/// Decode a known Harris opcode.
+fn decode_harris(tsbk: &TsbkFields) -> Option<VendorTsbk> {
+    let p = tsbk.payload();
+
+    match tsbk.raw_opcode() {
+        // Group regroup command, where only regroups of talkgroups (rather than of
+        // individual units) affect which calls are heard.
+        0x30 if p[0] & 0x40 != 0 => {
+            let sg = be16(&p[1..3]);
+            let tg = be16(&p[6..8]);
+
+            Some(VendorTsbk::Regroup(if p[0] & 0x20 != 0 {
+                Regroup::Add(sg, vec![tg])
+            } else {
+                Regroup::Delete(sg, vec![tg])
+            }))
+        },
+        _ => None,
+    }
+}
+
+/// Get the name of the given manufacturer, if known.
+pub fn vendor_name(mfg: u8) -> Option<&'static str> {
+    match mfg {
+        MFG_MOTOROLA => Some("motorola"),
+        MFG_HARRIS => Some("harris"),
+        _ => None,
+    }
+}
+
+/// Parse a 16-bit big-endian value.
+fn be16(b: &[u8]) -> u16 {
+    (b[0] as u16) << 8 | b[1] as u16
+}
+
+/// Parse a 24-bit big-endian value.
+fn be24(b: &[u8]) -> u32 {
+    (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32
+}
+
+/// Parse the member talkgroups in the given buffer, where unused slots are zero or
+/// repeat a previous member.
+fn members(b: &[u8]) -> Vec<u16> {
+    let mut tgs = Vec::with_capacity(b.len() / 2);
+
+    for chunk in b.chunks(2) {
+        let tg = be16(chunk);
+
+        if tg != 0 && !tgs.contains(&tg) {
+            tgs.push(tg);
+        }
+    }
+
+    tgs
+}
+
+/// Parse the 8-character callsign packed as 6-bit characters in the given 48 bits.
+fn callsign(b: &[u8]) -> String {
+    let bits = b.iter().fold(0u64, |s, &x| s << 8 | x as u64);
+
+    (0..8).map(|i| ((bits >> (42 - 6 * i)) & 0x3F) as u8)
+          .filter(|&c| c != 0)
+          .map(|c| (c + 0x2B) as char)
+          .collect()
+}
+
+/// Format the given bytes as uppercase hex.
+pub fn hex(b: &[u8]) -> String {
+    b.iter().map(|x| format!("{:02X}", x)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use signaling::build_tsbk;
+
+    #[test]
+    fn test_decode() {
+        let p = [0x01, 0xF4, 0x00, 0x0A, 0x00, 0x14, 0x00, 0x0A];
+        let t = TsbkFields::new(build_tsbk(MFG_MOTOROLA, 0x00, p));
+
+        match decode(&t) {
+            VendorTsbk::Regroup(Regroup::Add(500, ref tgs)) => assert_eq!(tgs, &[10, 20]),
+            _ => panic!(),
+        }
+
+        // Harris talkgroup regroup activation and deactivation.
+        let p = [0x61, 0x01, 0xF4, 0x00, 0x00, 0x80, 0x00, 0x0A];
+        let t = TsbkFields::new(build_tsbk(MFG_HARRIS, 0x30, p));
+
+        match decode(&t) {
+            VendorTsbk::Regroup(Regroup::Add(500, ref tgs)) => assert_eq!(tgs, &[10]),
+            _ => panic!(),
+        }
+
+        let p = [0x41, 0x01, 0xF4, 0x00, 0x00, 0x80, 0x00, 0x0A];
+        let t = TsbkFields::new(build_tsbk(MFG_HARRIS, 0x30, p));
+
+        match decode(&t) {
+            VendorTsbk::Regroup(Regroup::Delete(500, ref tgs)) => assert_eq!(tgs, &[10]),
+            _ => panic!(),
+        }
+
+        // Regroups of individual units aren't tracked.
+        let p = [0x21, 0x01, 0xF4, 0x00, 0x00, 0x00, 0x00, 0x0A];
+        let t = TsbkFields::new(build_tsbk(MFG_HARRIS, 0x30, p));
+
+        match decode(&t) {
+            VendorTsbk::Unknown(MFG_HARRIS, 0x30, _) => {},
+            _ => panic!(),
+        }
+
+        let t = TsbkFields::new(build_tsbk(0x01, 0x00, [0; 8]));
+
+        match decode(&t) {
+            VendorTsbk::Unknown(0x01, 0x00, _) => {},
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_members() {
+        assert_eq!(members(&[0x00, 0x01, 0x00, 0x02, 0x00, 0x01]), vec![1, 2]);
+        assert_eq!(members(&[0x12, 0x34, 0x00, 0x00, 0x00, 0x00]), vec![0x1234]);
+    }
+
+    #[test]
+    fn test_callsign() {
+        // "WQ1234" followed by two unused characters.
+        let chars = [0x2C, 0x26, 0x06, 0x07, 0x08, 0x09, 0x00, 0x00];
+        let bits = chars.iter().fold(0u64, |s, &c| s << 6 | c as u64);
+        let b = [(bits >> 40) as u8, (bits >> 32) as u8, (bits >> 24) as u8,
+                 (bits >> 16) as u8, (bits >> 8) as u8, bits as u8];
+
+        assert_eq!(callsign(&b), "WQ1234");
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(hex(&[0x01, 0xAB, 0xFF]), "01ABFF");
+        assert_eq!(hex(&[]), "");
+    }
+}
@@ -0,0 +1,340 @@
+//! WebSocket (RFC 6455) handshake and framing.
+
+use std::io::Write;
+use std;
+
+use base64;
+use sha1::Sha1;
+use uhttp_json_api::HttpResult;
+use uhttp_request::{self, Header};
+use uhttp_status::StatusCode;
+
+/// GUID appended to the client key when computing the handshake accept key.
+const GUID: &'static str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Maximum accepted payload size for a received frame (bytes).
+///
+/// Clients only send small command messages, so anything larger is treated as a
+/// protocol error.
+const MAX_PAYLOAD: usize = 1 << 16;
+
+/// Compute the `Sec-WebSocket-Accept` value for the given `Sec-WebSocket-Key`.
+pub fn accept_key(key: &str) -> String {
+    let mut sha = Sha1::new();
+    sha.update(key.as_bytes());
+    sha.update(GUID.as_bytes());
+
+    base64::encode(&sha.digest().bytes())
+}
+
+/// Check that the given request headers ask for a WebSocket upgrade, returning the
+/// `Sec-WebSocket-Accept` value for the response.
+pub fn handshake<'a, H>(headers: H) -> HttpResult<String>
+    where H: Iterator<Item = uhttp_request::Result<Header<'a>>>
+{
+    let mut upgrade = None;
+    let mut connection = None;
+    let mut version = None;
+    let mut key = None;
+
+    for h in headers {
+        let h = h.map_err(|_| StatusCode::BadRequest)?;
+        let val = std::str::from_utf8(h.val).map_err(|_| StatusCode::BadRequest)?.trim();
+
+        if h.name.eq_ignore_ascii_case("Upgrade") {
+            upgrade = Some(val);
+        } else if h.name.eq_ignore_ascii_case("Connection") {
+            connection = Some(val);
+        } else if h.name.eq_ignore_ascii_case("Sec-WebSocket-Version") {
+            version = Some(val);
+        } else if h.name.eq_ignore_ascii_case("Sec-WebSocket-Key") {
+            key = Some(val);
+        }
+    }
+
+    check_handshake(upgrade, connection, version, key)
+}
+
+/// Check the given upgrade, connection, version, and key header values of a handshake
+/// request.
+fn check_handshake(upgrade: Option<&str>, connection: Option<&str>,
+                   version: Option<&str>, key: Option<&str>)
+    -> HttpResult<String>
+{
+    // Both headers may list multiple comma-separated tokens.
+    let has_token = |val: Option<&str>, token: &str| val.map_or(false, |v| {
+        v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token))
+    });
+
+    if !has_token(upgrade, "websocket") || !has_token(connection, "upgrade") {
+        return Err(StatusCode::BadRequest);
+    }
+
+    if version != Some("13") {
+        return Err(StatusCode::UpgradeRequired);
+    }
+
+    match key {
+        Some(k) if !k.is_empty() => Ok(accept_key(k)),
+        _ => Err(StatusCode::BadRequest),
+    }
+}
+
+/// Frame types.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Opcode {
+    /// Continuation of a fragmented message.
+    Continuation,
+    /// UTF-8 text message.
+    Text,
+    /// Binary message.
+    Binary,
+    /// Connection close.
+    Close,
+    /// Ping request.
+    Ping,
+    /// Ping response.
+    Pong,
+}
+
+impl Opcode {
+    /// Parse the opcode from the given 4-bit value.
+    pub fn from_bits(bits: u8) -> Option<Opcode> {
+        use self::Opcode::*;
+
+        match bits {
+            0x0 => Some(Continuation),
+            0x1 => Some(Text),
+            0x2 => Some(Binary),
+            0x8 => Some(Close),
+            0x9 => Some(Ping),
+            0xA => Some(Pong),
+            _ => None,
+        }
+    }
+
+    /// Convert the opcode to its 4-bit value.
+    pub fn to_bits(&self) -> u8 {
+        use self::Opcode::*;
+
+        match *self {
+            Continuation => 0x0,
+            Text => 0x1,
+            Binary => 0x2,
+            Close => 0x8,
+            Ping => 0x9,
+            Pong => 0xA,
+        }
+    }
+}
+
+/// A received, unmasked frame.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Frame {
+    /// Frame type.
+    pub opcode: Opcode,
+    /// Unmasked payload.
+    pub payload: Vec<u8>,
+}
+
+/// Write a single unfragmented, unmasked frame with the given opcode and payload.
+pub fn write_frame<W: Write>(mut s: W, opcode: Opcode, payload: &[u8])
+    -> std::io::Result<()>
+{
+    let mut head = [0; 10];
+    head[0] = 0x80 | opcode.to_bits();
+
+    let len = payload.len();
+
+    let size = if len < 126 {
+        head[1] = len as u8;
+        2
+    } else if len <= 0xFFFF {
+        head[1] = 126;
+        head[2] = (len >> 8) as u8;
+        head[3] = len as u8;
+        4
+    } else {
+        head[1] = 127;
+
+        for i in 0..8 {
+            head[2 + i] = ((len as u64) >> (56 - i * 8)) as u8;
+        }
+
+        10
+    };
+
+    try!(s.write_all(&head[..size]));
+    s.write_all(payload)
+}
+
+/// Reassembles frames from the byte stream sent by a client.
+#[derive(Default)]
+pub struct FrameReader {
+    /// Bytes received but not yet consumed as a frame.
+    buf: Vec<u8>,
+}
+
+impl FrameReader {
+    /// Append the given received bytes.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Take the next complete frame from the received bytes.
+    ///
+    /// Return `Ok(None)` if more bytes are needed and `Err(())` if the client violated
+    /// the protocol.
+    pub fn next_frame(&mut self) -> Result<Option<Frame>, ()> {
+        if self.buf.len() < 2 {
+            return Ok(None);
+        }
+
+        // Fragmented messages are never needed for commands, so they aren't supported.
+        if self.buf[0] & 0x80 == 0 {
+            return Err(());
+        }
+
+        // Reserved bits must be zero without any negotiated extensions.
+        if self.buf[0] & 0x70 != 0 {
+            return Err(());
+        }
+
+        let opcode = match Opcode::from_bits(self.buf[0] & 0xF) {
+            Some(Opcode::Continuation) | None => return Err(()),
+            Some(o) => o,
+        };
+
+        // Clients must always mask their frames.
+        if self.buf[1] & 0x80 == 0 {
+            return Err(());
+        }
+
+        let (len, mut pos) = match self.buf[1] & 0x7F {
+            126 => {
+                if self.buf.len() < 4 {
+                    return Ok(None);
+                }
+
+                ((self.buf[2] as usize) << 8 | self.buf[3] as usize, 4)
+            },
+            127 => {
+                if self.buf.len() < 10 {
+                    return Ok(None);
+                }
+
+                let len = self.buf[2..10].iter().fold(0u64, |s, &b| s << 8 | b as u64);
+
+                if len > MAX_PAYLOAD as u64 {
+                    return Err(());
+                }
+
+                (len as usize, 10)
+            },
+            len => (len as usize, 2),
+        };
+
+        if len > MAX_PAYLOAD {
+            return Err(());
+        }
+
+        if self.buf.len() < pos + 4 + len {
+            return Ok(None);
+        }
+
+        let mut mask = [0; 4];
+        mask.copy_from_slice(&self.buf[pos..pos + 4]);
+        pos += 4;
+
+        let payload = self.buf[pos..pos + len].iter().enumerate()
+            .map(|(i, &b)| b ^ mask[i % 4])
+            .collect();
+
+        self.buf.drain(..pos + len);
+
+        Ok(Some(Frame {
+            opcode: opcode,
+            payload: payload,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_accept_key() {
+        // Example from RFC 6455 section 1.3.
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+                   "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn test_handshake() {
+        let key = Some("dGhlIHNhbXBsZSBub25jZQ==");
+
+        assert_eq!(check_handshake(Some("websocket"), Some("Upgrade"), Some("13"), key),
+                   Ok("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=".to_string()));
+        assert!(check_handshake(Some("WebSocket"), Some("keep-alive, Upgrade"),
+                                Some("13"), key).is_ok());
+        assert_eq!(check_handshake(None, Some("Upgrade"), Some("13"), key),
+                   Err(StatusCode::BadRequest));
+        assert_eq!(check_handshake(Some("websocket"), Some("keep-alive"), Some("13"),
+                                   key),
+                   Err(StatusCode::BadRequest));
+        assert_eq!(check_handshake(Some("websocket"), Some("Upgrade"), Some("8"), key),
+                   Err(StatusCode::UpgradeRequired));
+        assert_eq!(check_handshake(Some("websocket"), Some("Upgrade"), Some("13"), None),
+                   Err(StatusCode::BadRequest));
+    }
+
+    #[test]
+    fn test_write_frame() {
+        let mut buf = vec![];
+        write_frame(&mut buf, Opcode::Text, b"Hello").unwrap();
+        assert_eq!(&buf[..], &[0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f]);
+
+        let mut buf = vec![];
+        write_frame(&mut buf, Opcode::Binary, &[0; 256]).unwrap();
+        assert_eq!(&buf[..4], &[0x82, 0x7E, 0x01, 0x00]);
+        assert_eq!(buf.len(), 260);
+
+        let mut buf = vec![];
+        write_frame(&mut buf, Opcode::Binary, &[0; 65536]).unwrap();
+        assert_eq!(&buf[..10], &[0x82, 0x7F, 0, 0, 0, 0, 0, 1, 0, 0]);
+        assert_eq!(buf.len(), 65546);
+    }
+
+    #[test]
+    fn test_frame_reader() {
+        // Masked "Hello" example from RFC 6455 section 5.7.
+        let bytes = [0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58];
+
+        let mut r = FrameReader::default();
+        assert_eq!(r.next_frame(), Ok(None));
+        r.feed(&bytes[..4]);
+        assert_eq!(r.next_frame(), Ok(None));
+        r.feed(&bytes[4..]);
+        r.feed(&[0x89, 0x80, 1, 2, 3, 4]);
+        assert_eq!(r.next_frame(), Ok(Some(Frame {
+            opcode: Opcode::Text,
+            payload: b"Hello".to_vec(),
+        })));
+        assert_eq!(r.next_frame(), Ok(Some(Frame {
+            opcode: Opcode::Ping,
+            payload: vec![],
+        })));
+        assert_eq!(r.next_frame(), Ok(None));
+
+        // Unmasked client frame.
+        let mut r = FrameReader::default();
+        r.feed(&[0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f]);
+        assert_eq!(r.next_frame(), Err(()));
+
+        // Fragmented message.
+        let mut r = FrameReader::default();
+        r.feed(&[0x01, 0x81, 1, 2, 3, 4, 0]);
+        assert_eq!(r.next_frame(), Err(()));
+    }
+}